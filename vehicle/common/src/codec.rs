@@ -0,0 +1,63 @@
+// This code was developed by OpenTier GmbH.
+use prost::Message;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use zenoh::bytes::Encoding;
+
+// Messages that can be sent in every wire format. All vehicle_msgs types
+// qualify as they derive both prost and serde
+pub trait TopicMessage:
+    Message + Default + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+}
+
+impl<T> TopicMessage for T where
+    T: Message + Default + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+}
+
+// Wire format of the messages on a topic
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    #[default]
+    Protobuf,
+    Json,
+    Cbor,
+}
+
+impl WireFormat {
+    pub fn encoding(&self) -> Encoding {
+        match self {
+            WireFormat::Protobuf => Encoding::APPLICATION_PROTOBUF,
+            WireFormat::Json => Encoding::APPLICATION_JSON,
+            WireFormat::Cbor => Encoding::APPLICATION_CBOR,
+        }
+    }
+
+    pub fn encode<T: TopicMessage>(
+        &self,
+        message: &T,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            WireFormat::Protobuf => Ok(message.encode_to_vec()),
+            WireFormat::Json => Ok(serde_json::to_vec(message)?),
+            WireFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(message, &mut bytes)?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub fn decode<T: TopicMessage>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            WireFormat::Protobuf => Ok(T::decode(bytes)?),
+            WireFormat::Json => Ok(serde_json::from_slice(bytes)?),
+            WireFormat::Cbor => Ok(ciborium::from_reader(bytes)?),
+        }
+    }
+}
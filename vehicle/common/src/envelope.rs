@@ -0,0 +1,131 @@
+// This code was developed by OpenTier GmbH.
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use zenoh::bytes::ZBytes;
+
+// Metadata sent as Zenoh attachment along with every message of a typed topic
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope {
+    // Per publisher, starts at 0 and increases by one with every message
+    pub sequence: u64,
+    // Publish time as milliseconds since the Unix epoch
+    pub timestamp_ms: i64,
+    pub producer_id: String,
+    pub schema_version: u32,
+    // Inputs of the message that are no longer trustworthy, e.g. signals a
+    // cloud event was built from that stopped being updated
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stale_signals: Vec<String>,
+}
+
+impl Envelope {
+    pub fn to_attachment(&self) -> Result<ZBytes, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ZBytes::from(serde_json::to_vec(self)?))
+    }
+
+    pub fn from_attachment(
+        attachment: &ZBytes,
+    ) -> Result<Envelope, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(serde_json::from_slice(&attachment.to_bytes())?)
+    }
+
+    // Time since the message was published, negative if the clocks of the
+    // producer and this host disagree
+    pub fn age_ms(&self) -> i64 {
+        chrono::Utc::now().timestamp_millis() - self.timestamp_ms
+    }
+}
+
+// A message received on a typed topic with its envelope, which is missing if
+// the publisher doesn't send one
+#[derive(Debug, Clone)]
+pub struct Received<T> {
+    pub message: T,
+    pub envelope: Option<Envelope>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceCheck {
+    // First message of a producer
+    First,
+    InOrder,
+    // `missed` messages were lost before this one
+    Gap { missed: u64 },
+    // Older than or equal to the last message of the producer
+    Reordered,
+    // The producer started counting from 0 again
+    Restarted,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SequenceStats {
+    pub received: u64,
+    pub missed: u64,
+    pub reordered: u64,
+}
+
+// Tracks the sequence numbers of each producer of a topic to detect lost and
+// reordered messages
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    last_sequence: HashMap<String, u64>,
+    stats: SequenceStats,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check(&mut self, envelope: &Envelope) -> SequenceCheck {
+        self.stats.received += 1;
+        let sequence = envelope.sequence;
+        let Some(last) = self.last_sequence.get_mut(&envelope.producer_id) else {
+            self.last_sequence
+                .insert(envelope.producer_id.clone(), sequence);
+            return SequenceCheck::First;
+        };
+
+        if sequence == 0 && *last != 0 {
+            *last = sequence;
+            SequenceCheck::Restarted
+        } else if sequence <= *last {
+            self.stats.reordered += 1;
+            SequenceCheck::Reordered
+        } else if sequence == *last + 1 {
+            *last = sequence;
+            SequenceCheck::InOrder
+        } else {
+            let missed = sequence - *last - 1;
+            self.stats.missed += missed;
+            *last = sequence;
+            SequenceCheck::Gap { missed }
+        }
+    }
+
+    // Checks the envelope and logs lost and reordered messages
+    pub fn check_and_report(&mut self, key_expr: &str, envelope: &Envelope) -> SequenceCheck {
+        let check = self.check(envelope);
+        match check {
+            SequenceCheck::Gap { missed } => warn!(
+                "Missed {} message(s) from '{}' on '{}' before sequence {}",
+                missed, envelope.producer_id, key_expr, envelope.sequence
+            ),
+            SequenceCheck::Reordered => warn!(
+                "Out of order message {} from '{}' on '{}'",
+                envelope.sequence, envelope.producer_id, key_expr
+            ),
+            SequenceCheck::Restarted => info!(
+                "Producer '{}' on '{}' restarted its sequence",
+                envelope.producer_id, key_expr
+            ),
+            SequenceCheck::First | SequenceCheck::InOrder => {}
+        }
+        check
+    }
+
+    pub fn stats(&self) -> SequenceStats {
+        self.stats
+    }
+}
@@ -1,8 +1,18 @@
 // This code was developed by OpenTier GmbH.
+pub mod codec;
+pub mod envelope;
 pub mod publishers;
+pub mod session;
+pub mod signal;
 pub mod subscribers;
+pub mod topic;
 pub mod topics;
 
+pub use codec::*;
+pub use envelope::*;
 pub use publishers::*;
+pub use session::*;
+pub use signal::*;
 pub use subscribers::*;
+pub use topic::*;
 pub use topics::*;
@@ -1,7 +1,14 @@
 // This code was developed by OpenTier GmbH.
+use crate::codec::TopicMessage;
+use crate::envelope::Envelope;
+use crate::topic::Topic;
 use async_trait::async_trait;
 use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use zenoh::bytes::Encoding;
+use zenoh::key_expr::KeyExpr;
 use zenoh::pubsub::Publisher;
 use zenoh::Session;
 
@@ -18,10 +25,14 @@ pub struct ZenohPublisher<'a> {
 }
 
 impl<'a> ZenohPublisher<'a> {
-    pub async fn new(
+    pub async fn new<K>(
         session: Arc<Session>,
-        key_expr: &'static str,
-    ) -> Result<ZenohPublisher<'a>, Box<dyn std::error::Error + Send + Sync>> {
+        key_expr: K,
+    ) -> Result<ZenohPublisher<'a>, Box<dyn std::error::Error + Send + Sync>>
+    where
+        K: TryInto<KeyExpr<'a>>,
+        <K as TryInto<KeyExpr<'a>>>::Error: Into<zenoh::Error>,
+    {
         let publisher = session.declare_publisher(key_expr).await?;
         Ok(ZenohPublisher { publisher })
     }
@@ -37,3 +48,123 @@ where
         self.publisher.put(payload).await
     }
 }
+
+// Publisher of a typed topic, encoding messages in the topic's wire format and
+// attaching an `Envelope` to each of them
+pub struct TopicPublisher<T> {
+    topic: Topic<T>,
+    publisher: Publisher<'static>,
+    producer_id: String,
+    sequence: AtomicU64,
+}
+
+impl<T: TopicMessage> TopicPublisher<T> {
+    pub async fn new(
+        session: Arc<Session>,
+        topic: Topic<T>,
+    ) -> Result<TopicPublisher<T>, Box<dyn std::error::Error + Send + Sync>> {
+        let publisher = session
+            .declare_publisher(topic.key_expr().to_string())
+            .encoding(topic.format().encoding())
+            .await?;
+        Ok(TopicPublisher {
+            topic,
+            publisher,
+            producer_id: session.zid().to_string(),
+            sequence: AtomicU64::new(0),
+        })
+    }
+
+    // Replaces the default producer id, the Zenoh id of the session
+    pub fn with_producer_id(mut self, producer_id: impl Into<String>) -> Self {
+        self.producer_id = producer_id.into();
+        self
+    }
+
+    pub fn producer_id(&self) -> &str {
+        &self.producer_id
+    }
+
+    // Publishes a message flagged as built from the given stale signals
+    pub async fn publish_stale(
+        &self,
+        data: T,
+        stale_signals: Vec<String>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let message = self.encode(&data, stale_signals)?;
+        self.put(&message).await
+    }
+
+    // Encodes a message and assigns it the next sequence number, without publishing it
+    pub fn encode(
+        &self,
+        data: &T,
+        stale_signals: Vec<String>,
+    ) -> Result<EncodedMessage, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(EncodedMessage {
+            key_expr: self.topic.key_expr().to_string(),
+            encoding: self.topic.format().encoding().to_string(),
+            payload: self.topic.encode(data)?,
+            envelope: Envelope {
+                sequence: self.sequence.fetch_add(1, Ordering::Relaxed),
+                timestamp_ms: chrono::Utc::now().timestamp_millis(),
+                producer_id: self.producer_id.clone(),
+                schema_version: self.topic.schema_version(),
+                stale_signals,
+            },
+        })
+    }
+
+    pub async fn put(
+        &self,
+        message: &EncodedMessage,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.publisher
+            .put(message.payload.clone())
+            .attachment(message.envelope.to_attachment()?)
+            .await
+    }
+
+    // Whether anyone is subscribed to the topic, e.g. the cloud bridge
+    pub async fn has_subscribers(&self) -> bool {
+        self.publisher
+            .matching_status()
+            .await
+            .map(|status| status.matching())
+            .unwrap_or(false)
+    }
+
+    pub fn topic(&self) -> &Topic<T> {
+        &self.topic
+    }
+}
+
+// A message encoded by a `TopicPublisher`, which can be stored and put on its
+// key expression later on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncodedMessage {
+    pub key_expr: String,
+    pub encoding: String,
+    pub payload: Vec<u8>,
+    pub envelope: Envelope,
+}
+
+impl EncodedMessage {
+    pub async fn put(
+        &self,
+        session: &Session,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        session
+            .put(self.key_expr.clone(), self.payload.clone())
+            .encoding(Encoding::from(self.encoding.clone()))
+            .attachment(self.envelope.to_attachment()?)
+            .await
+    }
+}
+
+#[async_trait]
+impl<T: TopicMessage> DataPublisher<'static, T> for TopicPublisher<T> {
+    async fn publish(&self, data: T) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.publish_stale(data, Vec::new()).await
+    }
+}
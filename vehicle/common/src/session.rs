@@ -0,0 +1,114 @@
+// This code was developed by OpenTier GmbH.
+use clap::ValueEnum;
+use log::info;
+use serde::Deserialize;
+use std::sync::Arc;
+use zenoh::{Config, Session};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionMode {
+    Peer,
+    Client,
+    Router,
+}
+
+impl SessionMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SessionMode::Peer => "peer",
+            SessionMode::Client => "client",
+            SessionMode::Router => "router",
+        }
+    }
+}
+
+// Zenoh session options shared by all services. Can be set on the command line
+// (flattened into each service's `Args`) or in a service configuration file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize, clap::Args)]
+#[serde(default)]
+pub struct ZenohSessionConfig {
+    // Path to a Zenoh JSON5 configuration file used as the base configuration
+    #[arg(long = "zenoh-config")]
+    pub config_file: Option<String>,
+
+    // Zenoh session mode
+    #[arg(long = "zenoh-mode", value_enum)]
+    pub mode: Option<SessionMode>,
+
+    // Endpoints to connect to, e.g. tcp/127.0.0.1:7447
+    #[arg(long = "zenoh-connect")]
+    pub connect: Vec<String>,
+
+    // Endpoints to listen on, e.g. tcp/0.0.0.0:7447
+    #[arg(long = "zenoh-listen")]
+    pub listen: Vec<String>,
+
+    // Enable or disable multicast scouting
+    #[arg(long = "zenoh-multicast-scouting")]
+    pub multicast_scouting: Option<bool>,
+
+    // Scouting timeout in milliseconds
+    #[arg(long = "zenoh-scouting-timeout")]
+    pub scouting_timeout: Option<u64>,
+}
+
+impl ZenohSessionConfig {
+    // Returns a configuration where every option set in `overrides` replaces the one in `self`
+    pub fn merge(self, overrides: ZenohSessionConfig) -> ZenohSessionConfig {
+        ZenohSessionConfig {
+            config_file: overrides.config_file.or(self.config_file),
+            mode: overrides.mode.or(self.mode),
+            connect: if overrides.connect.is_empty() {
+                self.connect
+            } else {
+                overrides.connect
+            },
+            listen: if overrides.listen.is_empty() {
+                self.listen
+            } else {
+                overrides.listen
+            },
+            multicast_scouting: overrides.multicast_scouting.or(self.multicast_scouting),
+            scouting_timeout: overrides.scouting_timeout.or(self.scouting_timeout),
+        }
+    }
+
+    pub fn to_zenoh_config(&self) -> Result<Config, Box<dyn std::error::Error + Send + Sync>> {
+        let mut config = match &self.config_file {
+            Some(path) => Config::from_file(path)
+                .map_err(|e| format!("Failed to load Zenoh config '{}': {}", path, e))?,
+            None => Config::default(),
+        };
+
+        if let Some(mode) = self.mode {
+            config.insert_json5("mode", &serde_json::to_string(mode.as_str())?)?;
+        }
+        if !self.connect.is_empty() {
+            config.insert_json5("connect/endpoints", &serde_json::to_string(&self.connect)?)?;
+        }
+        if !self.listen.is_empty() {
+            config.insert_json5("listen/endpoints", &serde_json::to_string(&self.listen)?)?;
+        }
+        if let Some(enabled) = self.multicast_scouting {
+            config.insert_json5("scouting/multicast/enabled", &enabled.to_string())?;
+        }
+        if let Some(timeout) = self.scouting_timeout {
+            config.insert_json5("scouting/timeout", &timeout.to_string())?;
+        }
+
+        Ok(config)
+    }
+
+    pub async fn open(&self) -> Result<Arc<Session>, Box<dyn std::error::Error + Send + Sync>> {
+        let config = self.to_zenoh_config()?;
+        info!(
+            "Opening Zenoh session (mode: {:?}, connect: {:?}, listen: {:?})",
+            self.mode, self.connect, self.listen
+        );
+        let session = zenoh::open(config)
+            .await
+            .map_err(|e| format!("Failed to open Zenoh session: {}", e))?;
+        Ok(Arc::new(session))
+    }
+}
@@ -0,0 +1,25 @@
+// This code was developed by OpenTier GmbH.
+
+// Resolves with the name of the first shutdown signal the process receives,
+// SIGINT or SIGTERM
+#[cfg(unix)]
+pub async fn os_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => tokio::select! {
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+            _ = sigterm.recv() => "SIGTERM",
+        },
+        Err(e) => {
+            log::warn!("Failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            "SIGINT"
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn os_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "Ctrl-C"
+}
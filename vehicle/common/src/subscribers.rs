@@ -1,10 +1,14 @@
 // This code was developed by OpenTier GmbH.
-use log::error;
-use prost::Message;
+use crate::codec::TopicMessage;
+use crate::envelope::{Envelope, Received, SequenceStats, SequenceTracker};
+use crate::topic::Topic;
+use log::{error, warn};
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
+use zenoh::bytes::Encoding;
 use zenoh::handlers::FifoChannelHandler;
+use zenoh::key_expr::KeyExpr;
 use zenoh::pubsub::Subscriber;
 use zenoh::sample::Sample;
 use zenoh::Session;
@@ -14,48 +18,175 @@ pub struct ZenohSubscriber {
 }
 
 impl ZenohSubscriber {
-    pub async fn new(
+    pub async fn new<'a, K>(
         session: Arc<Session>,
-        key_expr: &'static str,
-    ) -> Result<ZenohSubscriber, Box<dyn std::error::Error + Send + Sync>> {
+        key_expr: K,
+    ) -> Result<ZenohSubscriber, Box<dyn std::error::Error + Send + Sync>>
+    where
+        K: TryInto<KeyExpr<'a>>,
+        <K as TryInto<KeyExpr<'a>>>::Error: Into<zenoh::Error>,
+    {
         let subscriber = session.declare_subscriber(key_expr).await?;
         Ok(ZenohSubscriber { subscriber })
     }
 }
 
+// Subscriber of a typed topic, decoding messages from the topic's wire format
+// and checking the sequence numbers of their envelopes
+pub struct TopicSubscriber<T> {
+    topic: Topic<T>,
+    subscriber: ZenohSubscriber,
+    tracker: SequenceTracker,
+}
+
+impl<T: TopicMessage> TopicSubscriber<T> {
+    pub async fn new(
+        session: Arc<Session>,
+        topic: Topic<T>,
+    ) -> Result<TopicSubscriber<T>, Box<dyn std::error::Error + Send + Sync>> {
+        let subscriber = ZenohSubscriber::new(session, topic.key_expr().to_string()).await?;
+        Ok(TopicSubscriber {
+            topic,
+            subscriber,
+            tracker: SequenceTracker::new(),
+        })
+    }
+
+    pub fn topic(&self) -> &Topic<T> {
+        &self.topic
+    }
+
+    // Received, lost and reordered messages so far
+    pub fn sequence_stats(&self) -> SequenceStats {
+        self.tracker.stats()
+    }
+
+    // Waits for the next message, None once the subscriber is closed
+    pub async fn recv(&mut self) -> Option<Result<T, Box<dyn std::error::Error + Send + Sync>>> {
+        let received = self.recv_with_metadata().await?;
+        Some(received.map(|received| received.message))
+    }
+
+    // Waits for the next message and its envelope, None once the subscriber is closed
+    pub async fn recv_with_metadata(
+        &mut self,
+    ) -> Option<Result<Received<T>, Box<dyn std::error::Error + Send + Sync>>> {
+        let sample = self.subscriber.subscriber.recv_async().await.ok()?;
+        let envelope = self.envelope(&sample);
+        Some(
+            self.decode(&sample)
+                .map(|message| Received { message, envelope }),
+        )
+    }
+
+    fn envelope(&mut self, sample: &Sample) -> Option<Envelope> {
+        let key_expr = self.topic.key_expr();
+        let envelope = match Envelope::from_attachment(sample.attachment()?) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                warn!("Ignoring invalid envelope on '{}': {}", key_expr, e);
+                return None;
+            }
+        };
+        if envelope.schema_version != self.topic.schema_version() {
+            warn!(
+                "Message from '{}' on '{}' has schema version {}, expected {}",
+                envelope.producer_id,
+                key_expr,
+                envelope.schema_version,
+                self.topic.schema_version()
+            );
+        }
+        self.tracker.check_and_report(key_expr, &envelope);
+        Some(envelope)
+    }
+
+    pub fn decode(&self, sample: &Sample) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+        // Samples without encoding come from publishers that predate typed topics
+        let expected = self.topic.format().encoding();
+        let encoding = sample.encoding();
+        if *encoding != Encoding::default() && *encoding != expected {
+            return Err(format!(
+                "Unexpected encoding {} on '{}', expected {}",
+                encoding,
+                self.topic.key_expr(),
+                expected
+            )
+            .into());
+        }
+        self.topic.decode(&sample.payload().to_bytes())
+    }
+}
+
 pub struct SubscriberTaskSpawner;
 
 impl SubscriberTaskSpawner {
     pub fn spawn_task<T>(
         session: Arc<Session>,
-        key_expr: &'static str,
+        topic: Topic<T>,
         sender: mpsc::Sender<T>,
     ) -> JoinHandle<()>
     where
-        T: Message + Default + Send + Sync + 'static,
+        T: TopicMessage,
     {
-        tokio::spawn(async move {
-            match ZenohSubscriber::new(session, key_expr).await {
-                Ok(subscriber) => {
-                    while let Ok(sample) = subscriber.subscriber.recv_async().await {
-                        let bytes = sample.payload().to_bytes();
-                        match T::decode(&*bytes) {
-                            Ok(message) => {
-                                if let Err(err) = sender.send(message).await {
-                                    error!("Failed to send message through channel: {:?}", err);
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                error!("Failed to decode message: {:?}", e);
+        tokio::spawn(Self::forward(session, topic, sender))
+    }
+
+    pub fn spawn_task_with_metadata<T>(
+        session: Arc<Session>,
+        topic: Topic<T>,
+        sender: mpsc::Sender<Received<T>>,
+    ) -> JoinHandle<()>
+    where
+        T: TopicMessage,
+    {
+        tokio::spawn(Self::forward_with_metadata(session, topic, sender))
+    }
+
+    // Decodes the samples received on `topic` and sends them through
+    // `sender`, returns once the subscriber or the channel is closed
+    pub async fn forward<T>(session: Arc<Session>, topic: Topic<T>, sender: mpsc::Sender<T>)
+    where
+        T: TopicMessage,
+    {
+        Self::run(session, topic, sender, |received| received.message).await
+    }
+
+    // Like `forward`, but sends the messages along with their envelopes
+    pub async fn forward_with_metadata<T>(
+        session: Arc<Session>,
+        topic: Topic<T>,
+        sender: mpsc::Sender<Received<T>>,
+    ) where
+        T: TopicMessage,
+    {
+        Self::run(session, topic, sender, |received| received).await
+    }
+
+    async fn run<T, M, F>(session: Arc<Session>, topic: Topic<T>, sender: mpsc::Sender<M>, map: F)
+    where
+        T: TopicMessage,
+        F: Fn(Received<T>) -> M,
+    {
+        match TopicSubscriber::new(session, topic).await {
+            Ok(mut subscriber) => {
+                while let Some(result) = subscriber.recv_with_metadata().await {
+                    match result {
+                        Ok(received) => {
+                            if sender.send(map(received)).await.is_err() {
+                                error!("Failed to send message through channel: receiver dropped");
+                                break;
                             }
                         }
+                        Err(e) => {
+                            error!("Failed to decode message: {:?}", e);
+                        }
                     }
                 }
-                Err(e) => {
-                    error!("Failed to create subscriber: {:?}", e)
-                }
-            };
-        })
+            }
+            Err(e) => {
+                error!("Failed to create subscriber: {:?}", e)
+            }
+        };
     }
 }
@@ -0,0 +1,94 @@
+// This code was developed by OpenTier GmbH.
+use crate::codec::{TopicMessage, WireFormat};
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+
+// A key expression together with the type and wire format of its messages, so
+// that publishers and subscribers of a topic always agree on both
+pub struct Topic<T> {
+    key_expr: Cow<'static, str>,
+    format: WireFormat,
+    schema_version: u32,
+    message: PhantomData<fn() -> T>,
+}
+
+// Schema version of topics that don't set one
+pub const DEFAULT_SCHEMA_VERSION: u32 = 1;
+
+impl<T: TopicMessage> Topic<T> {
+    // Protobuf topic on a fixed key expression
+    pub const fn new(key_expr: &'static str) -> Self {
+        Self {
+            key_expr: Cow::Borrowed(key_expr),
+            format: WireFormat::Protobuf,
+            schema_version: DEFAULT_SCHEMA_VERSION,
+            message: PhantomData,
+        }
+    }
+
+    // Topic on a key expression only known at runtime, e.g. from a config file
+    pub fn from_key_expr(key_expr: impl Into<String>, format: WireFormat) -> Self {
+        Self {
+            key_expr: Cow::Owned(key_expr.into()),
+            format,
+            schema_version: DEFAULT_SCHEMA_VERSION,
+            message: PhantomData,
+        }
+    }
+
+    pub fn with_format(self, format: WireFormat) -> Self {
+        Self { format, ..self }
+    }
+
+    // Version of the message schema, to be bumped on incompatible changes
+    pub fn with_schema_version(self, schema_version: u32) -> Self {
+        Self {
+            schema_version,
+            ..self
+        }
+    }
+
+    pub fn key_expr(&self) -> &str {
+        &self.key_expr
+    }
+
+    pub fn format(&self) -> WireFormat {
+        self.format
+    }
+
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    pub fn encode(&self, message: &T) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+        self.format.encode(message)
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+        self.format.decode(bytes)
+    }
+}
+
+// Implemented by hand as the derives would require `T: Clone` and `T: Debug`
+impl<T> Clone for Topic<T> {
+    fn clone(&self) -> Self {
+        Self {
+            key_expr: self.key_expr.clone(),
+            format: self.format,
+            schema_version: self.schema_version,
+            message: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Topic<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Topic")
+            .field("key_expr", &self.key_expr)
+            .field("format", &self.format)
+            .field("schema_version", &self.schema_version)
+            .field("message", &std::any::type_name::<T>())
+            .finish()
+    }
+}
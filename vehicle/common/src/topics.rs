@@ -1,4 +1,16 @@
 // This code was developed by OpenTier GmbH.
+use crate::topic::Topic;
+use vehicle_msgs::battery::BatteryData;
+use vehicle_msgs::current_location::CurrentLocation;
+use vehicle_msgs::exterior::Exterior;
+use vehicle_msgs::horn::Horn;
+use vehicle_msgs::lights::Lights;
+use vehicle_msgs::powertrain::PowertrainState;
+use vehicle_msgs::speed::Speed;
+use vehicle_msgs::state::LockState;
+use vehicle_msgs::tires::Tires;
+use vehicle_msgs::trip_data::TripData;
+
 pub const SPEED_TOPIC: &str = "speed";
 pub const EXTERIOR_TOPIC: &str = "exterior";
 pub const CURRENT_LOCATION_TOPIC: &str = "location";
@@ -6,3 +18,53 @@ pub const LOCK_STATE_TOPIC: &str = "lock_state";
 pub const TRIP_DATA_TOPIC: &str = "trip_data";
 pub const BATTERY_STATE_TOPIC: &str = "battery_state";
 pub const TIRES_TOPIC: &str = "tires";
+pub const LIGHTS_TOPIC: &str = "lights";
+pub const HORN_TOPIC: &str = "horn";
+pub const POWERTRAIN_TOPIC: &str = "powertrain";
+
+// Commands for the in-vehicle actuators, which report the resulting state on
+// the topics above
+pub const LOCK_STATE_COMMAND_TOPIC: &str = "lock_state/set";
+pub const LIGHTS_COMMAND_TOPIC: &str = "lights/set";
+pub const HORN_COMMAND_TOPIC: &str = "horn/set";
+pub const POWERTRAIN_COMMAND_TOPIC: &str = "powertrain/set";
+
+// Typed topics of the intra-vehicle messages
+pub const SPEED: Topic<Speed> = Topic::new(SPEED_TOPIC);
+pub const EXTERIOR: Topic<Exterior> = Topic::new(EXTERIOR_TOPIC);
+pub const CURRENT_LOCATION: Topic<CurrentLocation> = Topic::new(CURRENT_LOCATION_TOPIC);
+pub const LOCK_STATE: Topic<LockState> = Topic::new(LOCK_STATE_TOPIC);
+pub const TRIP_DATA: Topic<TripData> = Topic::new(TRIP_DATA_TOPIC);
+pub const BATTERY_STATE: Topic<BatteryData> = Topic::new(BATTERY_STATE_TOPIC);
+pub const TIRES: Topic<Tires> = Topic::new(TIRES_TOPIC);
+pub const LIGHTS: Topic<Lights> = Topic::new(LIGHTS_TOPIC);
+pub const HORN: Topic<Horn> = Topic::new(HORN_TOPIC);
+pub const POWERTRAIN: Topic<PowertrainState> = Topic::new(POWERTRAIN_TOPIC);
+
+// Typed topics of the actuator commands
+pub const LOCK_STATE_COMMAND: Topic<LockState> = Topic::new(LOCK_STATE_COMMAND_TOPIC);
+pub const LIGHTS_COMMAND: Topic<Lights> = Topic::new(LIGHTS_COMMAND_TOPIC);
+pub const HORN_COMMAND: Topic<Horn> = Topic::new(HORN_COMMAND_TOPIC);
+pub const POWERTRAIN_COMMAND: Topic<PowertrainState> = Topic::new(POWERTRAIN_COMMAND_TOPIC);
+
+// All intra-vehicle topics
+pub const VEHICLE_TOPICS: &[&str] = &[
+    SPEED_TOPIC,
+    EXTERIOR_TOPIC,
+    CURRENT_LOCATION_TOPIC,
+    LOCK_STATE_TOPIC,
+    TRIP_DATA_TOPIC,
+    BATTERY_STATE_TOPIC,
+    TIRES_TOPIC,
+    LIGHTS_TOPIC,
+    HORN_TOPIC,
+    POWERTRAIN_TOPIC,
+];
+
+// All actuator command topics
+pub const COMMAND_TOPICS: &[&str] = &[
+    LOCK_STATE_COMMAND_TOPIC,
+    LIGHTS_COMMAND_TOPIC,
+    HORN_COMMAND_TOPIC,
+    POWERTRAIN_COMMAND_TOPIC,
+];
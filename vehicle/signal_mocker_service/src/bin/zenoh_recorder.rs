@@ -0,0 +1,114 @@
+// This code was developed by OpenTier GmbH.
+use clap::Parser;
+use common::topics::{COMMAND_TOPICS, VEHICLE_TOPICS};
+use common::{os_signal, ZenohSessionConfig, ZenohSubscriber};
+use log::{error, info};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::time::{interval, Duration};
+use vehicle_msgs::vehicle_recording::RecordedSample;
+
+// Key expression of the traffic between the vehicle and the cloud
+const CLOUD_KEY_EXPR: &str = "cloud/**";
+
+// Records Zenoh traffic to a file that signal_mocker_service can replay
+#[derive(Parser, Clone, PartialEq, Eq, Hash, Debug)]
+struct Args {
+    // Path of the recording to write
+    #[arg(short, long)]
+    output: String,
+
+    // Key expressions to record, the intra-vehicle and command topics and
+    // cloud/** if none
+    #[arg(short, long = "key-expr")]
+    key_exprs: Vec<String>,
+
+    // Zenoh session options
+    #[command(flatten)]
+    zenoh: ZenohSessionConfig,
+}
+
+#[tokio::main(flavor = "multi_thread", worker_threads = 2)]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let args = Args::parse();
+
+    // Initialize logger
+    env_logger::init();
+
+    let key_exprs = if args.key_exprs.is_empty() {
+        VEHICLE_TOPICS
+            .iter()
+            .chain(COMMAND_TOPICS)
+            .map(|topic| topic.to_string())
+            .chain([CLOUD_KEY_EXPR.to_string()])
+            .collect()
+    } else {
+        args.key_exprs
+    };
+
+    let mut writer = BufWriter::new(
+        File::create(&args.output)
+            .map_err(|e| format!("Failed to create recording {}: {}", args.output, e))?,
+    );
+    let zenoh_session = args.zenoh.open().await?;
+
+    // Samples of all subscribers are written by a single task
+    let (sample_tx, mut sample_rx) = mpsc::channel::<RecordedSample>(1024);
+    for key_expr in key_exprs {
+        let subscriber = ZenohSubscriber::new(zenoh_session.clone(), key_expr.clone()).await?;
+        let sample_tx = sample_tx.clone();
+        info!("Recording '{}'", key_expr);
+        tokio::spawn(async move {
+            while let Ok(sample) = subscriber.subscriber.recv_async().await {
+                let recorded = RecordedSample {
+                    timestamp_us: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_micros() as u64,
+                    key_expr: sample.key_expr().to_string(),
+                    encoding: sample.encoding().to_string(),
+                    payload: sample.payload().to_bytes().to_vec(),
+                    attachment: sample
+                        .attachment()
+                        .map(|attachment| attachment.to_bytes().to_vec())
+                        .unwrap_or_default(),
+                };
+                if sample_tx.send(recorded).await.is_err() {
+                    return;
+                }
+            }
+            // The session also closes when the recording is stopped
+            if !sample_tx.is_closed() {
+                error!("Subscriber for '{}' closed", key_expr);
+            }
+        });
+    }
+    drop(sample_tx);
+
+    info!("Recording to {}, stop with Ctrl-C", args.output);
+    let mut recorded = 0u64;
+    let mut flush = interval(Duration::from_secs(1));
+    let stop = os_signal();
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            sample = sample_rx.recv() => {
+                let Some(sample) = sample else { break };
+                sample.write_to(&mut writer)?;
+                recorded += 1;
+            }
+            _ = flush.tick() => writer.flush()?,
+            signal = &mut stop => {
+                info!("Received {}, stopping the recording", signal);
+                break;
+            }
+        }
+    }
+    drop(sample_rx);
+    writer.flush()?;
+    info!("Recorded {} samples to {}", recorded, args.output);
+
+    Ok(())
+}
@@ -1,3 +1,5 @@
+use crate::time_series::TimeSeries;
+use common::ZenohSessionConfig;
 use serde::Deserialize;
 use std::collections::HashMap;
 
@@ -8,29 +10,210 @@ pub struct RootConfig {
 
 #[derive(Debug, Deserialize)]
 pub struct SignalMockerServiceConfig {
+    #[serde(default)]
+    pub zenoh: ZenohSessionConfig,
     pub messages: HashMap<String, MessageConfig>,
+    // Drives Speed, TripData, BatteryData and CurrentLocation from one vehicle model
+    #[serde(default)]
+    pub scenario: Option<ScenarioConfig>,
+    // Republishes a recording instead of generating any message
+    #[serde(default)]
+    pub replay: Option<ReplayConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReplayConfig {
+    // Recording written by the zenoh_recorder
+    pub file: String,
+    // Time scale, e.g. 2.0 replays twice as fast as recorded
+    #[serde(default = "default_replay_speed")]
+    pub speed: f64,
+    // Start over at the beginning after the last sample
+    #[serde(default)]
+    pub repeat: bool,
+    // Key expressions of the samples to replay, all of them if empty
+    #[serde(default)]
+    pub topics: Vec<String>,
+}
+
+fn default_replay_speed() -> f64 {
+    1.0
 }
 
 #[derive(Debug, Deserialize)]
 pub struct MessageConfig {
     pub frequency: u64,
+    #[serde(default)]
     pub signals: HashMap<String, SignalOrNestedMessage>,
+    // Route replayed by the CurrentLocation generator instead of its
+    // Latitude, Longitude, Altitude and Heading signals
+    #[serde(default)]
+    pub route: Option<RouteConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScenarioConfig {
+    // Simulation step, also the publication frequency of scenario messages
+    // without an entry in `messages`
+    #[serde(default = "default_scenario_tick")]
+    pub tick: u64, // in milliseconds
+    // Drive profile, played in order
+    pub phases: Vec<DrivePhase>,
+    // Start over with the first phase after the last one
+    #[serde(default = "default_scenario_repeat")]
+    pub repeat: bool,
+    #[serde(default)]
+    pub vehicle: VehicleModelConfig,
+    pub route: RouteConfig,
+}
+
+fn default_scenario_tick() -> u64 {
+    200
+}
+
+fn default_scenario_repeat() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "phase", rename_all = "lowercase")]
+pub enum DrivePhase {
+    // Changes the speed to `speed` km/h at `acceleration` m/s²
+    Accelerate {
+        speed: f64,
+        #[serde(default = "default_acceleration")]
+        acceleration: f64,
+    },
+    // Keeps the current speed
+    Cruise {
+        duration: u64, // in milliseconds
+    },
+    // Brakes to a standstill at `deceleration` m/s² and stands for `duration`
+    Stop {
+        #[serde(default = "default_deceleration")]
+        deceleration: f64,
+        #[serde(default)]
+        duration: u64, // in milliseconds
+    },
+    // Charges at `power` kW until the battery is at `battery_level` percent
+    Charge {
+        #[serde(default = "default_charge_power")]
+        power: f64,
+        battery_level: f64,
+    },
+}
+
+fn default_acceleration() -> f64 {
+    2.0
+}
+
+fn default_deceleration() -> f64 {
+    3.0
+}
+
+fn default_charge_power() -> f64 {
+    50.0
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct VehicleModelConfig {
+    pub battery_capacity: f64,      // in kWh
+    pub consumption: f64,           // in kWh/100km
+    pub initial_battery_level: f64, // in percent
+    pub state_of_health: f64,       // in percent
+    pub battery_temperature: f64,   // in °C
+    pub odometer: f64,              // in km
+}
+
+impl Default for VehicleModelConfig {
+    fn default() -> Self {
+        Self {
+            battery_capacity: 75.0,
+            consumption: 18.0,
+            initial_battery_level: 80.0,
+            state_of_health: 98.0,
+            battery_temperature: 25.0,
+            odometer: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RouteConfig {
+    // Waypoints driven through in order
+    #[serde(default)]
+    pub points: Vec<RoutePoint>,
+    // GPX track or route, or GeoJSON LineString file used instead of the points
+    #[serde(default)]
+    pub file: Option<String>,
+    // What happens at the end of the route
+    #[serde(default)]
+    pub end: RouteEnd,
+    // Playback speed in km/h when replayed by the CurrentLocation generator
+    // without a Speed signal
+    #[serde(default = "default_route_speed")]
+    pub speed: f64,
+}
+
+fn default_route_speed() -> f64 {
+    50.0
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteEnd {
+    // Start over at the first point
+    #[default]
+    Loop,
+    // Drive the route back and forth
+    PingPong,
+    // Stay at the last point
+    Stop,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct RoutePoint {
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default)]
+    pub altitude: f64,
 }
 
 // This enum allows a signal to either be a regular signal or a nested message
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 pub enum SignalOrNestedMessage {
-    Signal(SignalConfig),
+    Signal(Box<SignalConfig>),
     NestedMessage(HashMap<String, SignalOrNestedMessage>),
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum DataType {
     Static,
     Interpolated,
     Timestamp,
+    // Recorded values from a CSV or Parquet file
+    #[serde(rename = "time_series")]
+    TimeSeries,
+    // Periodic waveforms around `offset` with `amplitude`, `period` and `phase`
+    Sine,
+    Square,
+    Triangle,
+    // Random steps of up to `step_size` from `start_value`, within the bounds
+    #[serde(rename = "random_walk")]
+    RandomWalk,
+    // Normally distributed values with `mean` and `std_dev`, within the bounds
+    Gaussian,
+    // `start_value` until `at`, `end_value` from then on
+    Step,
+    // `end_value` for `width` from `at`, repeated every `period` if set,
+    // `start_value` otherwise
+    Pulse,
+    // Linear interpolation between [time, value] keyframes, repeated after
+    // the last one
+    Keyframes,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -39,10 +222,178 @@ pub struct SignalConfig {
     pub data: Option<Vec<f64>>, // For numeric data (static/interpolated)
     pub data_string: Option<Vec<String>>, // For static string data
     pub data_bool: Option<Vec<bool>>, // For static bool data
-    pub start_value: Option<f64>, // For interpolated data
-    pub end_value: Option<f64>, // For interpolated data
+    pub start_value: Option<f64>, // For interpolated, random walk, step and pulse data
+    pub end_value: Option<f64>, // For interpolated, step and pulse data
     pub steps: Option<u64>,     // For interpolated data
     pub noise_level: Option<f64>, // For interpolated data
+    pub file: Option<String>,   // For time series data: CSV or Parquet file
+    pub timestamp_column: Option<String>, // For time series data: the first column if not set
+    pub value_column: Option<String>, // For time series data: the second column if not set
+    pub amplitude: Option<f64>, // For waveforms, 1 if not set
+    pub offset: Option<f64>,    // For waveforms: center value, 0 if not set
+    pub period: Option<u64>,    // For waveforms and pulses: in milliseconds
+    pub phase: Option<f64>,     // For waveforms: in degrees
+    pub duty_cycle: Option<f64>, // For square waves: high fraction of the period, 0.5 if not set
+    pub step_size: Option<f64>, // For random walks: largest change per value
+    pub mean: Option<f64>,      // For gaussian data
+    pub std_dev: Option<f64>,   // For gaussian data
+    pub min_value: Option<f64>, // For random walks and gaussian data: lower bound
+    pub max_value: Option<f64>, // For random walks and gaussian data: upper bound
+    pub at: Option<u64>,        // For step and pulse data: in milliseconds since the start
+    pub width: Option<u64>,     // For pulse data: in milliseconds
+    pub keyframes: Option<Vec<(f64, f64)>>, // For keyframes data: [milliseconds, value] pairs
+}
+
+impl SignalMockerServiceConfig {
+    // Loads the files of time series signals and resamples them to the
+    // frequency of their message, as values played like static data
+    pub fn load_time_series(&mut self) -> Result<(), String> {
+        for message in self.messages.values_mut() {
+            let period = message.frequency as f64 / 1000.0;
+            load_time_series(&mut message.signals, period)?;
+        }
+        Ok(())
+    }
+}
+
+impl SignalMockerServiceConfig {
+    // Checks that every signal has the fields its data type needs, so that a
+    // broken signal fails the start instead of publishing nothing
+    pub fn validate_signals(&self) -> Result<(), String> {
+        for (name, message) in &self.messages {
+            validate_signals(name, &message.signals)?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_signals(
+    prefix: &str,
+    signals: &HashMap<String, SignalOrNestedMessage>,
+) -> Result<(), String> {
+    for (name, signal) in signals {
+        let name = format!("{}.{}", prefix, name);
+        match signal {
+            SignalOrNestedMessage::Signal(signal) => signal
+                .validate()
+                .map_err(|e| format!("Invalid signal {}: {}", name, e))?,
+            SignalOrNestedMessage::NestedMessage(nested) => validate_signals(&name, nested)?,
+        }
+    }
+    Ok(())
+}
+
+impl SignalConfig {
+    fn validate(&self) -> Result<(), String> {
+        match self.data_type {
+            DataType::Static | DataType::TimeSeries => {
+                let lengths = [
+                    self.data.as_ref().map(Vec::len),
+                    self.data_string.as_ref().map(Vec::len),
+                    self.data_bool.as_ref().map(Vec::len),
+                ];
+                if lengths.iter().all(Option::is_none) || lengths.contains(&Some(0)) {
+                    return Err("data, data_string or data_bool must not be empty".to_string());
+                }
+            }
+            DataType::Interpolated => {
+                required(self.start_value, "start_value")?;
+                required(self.end_value, "end_value")?;
+                if required(self.steps, "steps")? == 0 {
+                    return Err("steps must be positive".to_string());
+                }
+                if self
+                    .noise_level
+                    .is_some_and(|noise_level| noise_level < 0.0)
+                {
+                    return Err("noise_level must not be negative".to_string());
+                }
+            }
+            DataType::Timestamp => {}
+            DataType::Sine | DataType::Square | DataType::Triangle => {
+                if required(self.period, "period")? == 0 {
+                    return Err("period must be positive".to_string());
+                }
+                if self
+                    .duty_cycle
+                    .is_some_and(|duty_cycle| !(0.0..=1.0).contains(&duty_cycle))
+                {
+                    return Err("duty_cycle must be between 0 and 1".to_string());
+                }
+            }
+            DataType::RandomWalk => {
+                if self
+                    .step_size
+                    .is_some_and(|step_size| !step_size.is_finite())
+                {
+                    return Err("step_size must be a finite number".to_string());
+                }
+                self.validate_bounds()?;
+            }
+            DataType::Gaussian => {
+                if self
+                    .std_dev
+                    .is_some_and(|std_dev| !std_dev.is_finite() || std_dev < 0.0)
+                {
+                    return Err("std_dev must not be negative".to_string());
+                }
+                self.validate_bounds()?;
+            }
+            DataType::Step => {
+                required(self.start_value, "start_value")?;
+                required(self.end_value, "end_value")?;
+            }
+            DataType::Pulse => {
+                required(self.start_value, "start_value")?;
+                required(self.end_value, "end_value")?;
+                required(self.width, "width")?;
+            }
+            DataType::Keyframes => {
+                if self.keyframes.as_ref().is_none_or(Vec::is_empty) {
+                    return Err("keyframes must not be empty".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_bounds(&self) -> Result<(), String> {
+        match (self.min_value, self.max_value) {
+            (Some(min), Some(max)) if min > max => {
+                Err("min_value must not be above max_value".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn required<T>(value: Option<T>, field: &str) -> Result<T, String> {
+    value.ok_or_else(|| format!("{} is missing", field))
+}
+
+fn load_time_series(
+    signals: &mut HashMap<String, SignalOrNestedMessage>,
+    period: f64,
+) -> Result<(), String> {
+    for (name, signal) in signals.iter_mut() {
+        match signal {
+            SignalOrNestedMessage::Signal(signal) if signal.data_type == DataType::TimeSeries => {
+                let file = signal
+                    .file
+                    .as_deref()
+                    .ok_or_else(|| format!("Time series signal {} needs a file", name))?;
+                let series = TimeSeries::load(
+                    file,
+                    signal.timestamp_column.as_deref(),
+                    signal.value_column.as_deref(),
+                )?;
+                signal.data = Some(series.resample(period));
+            }
+            SignalOrNestedMessage::Signal(_) => {}
+            SignalOrNestedMessage::NestedMessage(nested) => load_time_series(nested, period)?,
+        }
+    }
+    Ok(())
 }
 
 pub fn extract_signals(
@@ -53,7 +404,7 @@ pub fn extract_signals(
     for (key, value) in input {
         match value {
             SignalOrNestedMessage::Signal(signal) => {
-                result.insert(key.clone(), signal.clone());
+                result.insert(key.clone(), (**signal).clone());
             }
             SignalOrNestedMessage::NestedMessage(_) => {
                 // Handle nested messages if needed, or skip them
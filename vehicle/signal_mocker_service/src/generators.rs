@@ -1,7 +1,11 @@
 // This code was developed by OpenTier GmbH.
 use crate::config::*;
+use crate::time_series::interpolate;
 use rand::Rng;
+use rand_distr::{Distribution, Normal};
 use std::collections::HashMap;
+use std::f64::consts::{PI, TAU};
+use std::time::Instant;
 
 #[derive(Debug, Clone)]
 struct SignalState {
@@ -19,12 +23,20 @@ where
 pub struct SignalGenerator {
     config: HashMap<String, SignalConfig>, // Store the configuration for signals
     signal_state: HashMap<String, SignalState>, // Store the state of each signal
+    started: Instant,                      // Time base of waveforms and events
 }
 
 impl SignalGenerator {
-    pub fn new(config: HashMap<String, SignalConfig>) -> Self {
+    pub fn new(mut config: HashMap<String, SignalConfig>) -> Self {
         let mut signal_state = HashMap::new();
 
+        // Keyframes may be configured in any order
+        for signal in config.values_mut() {
+            if let Some(keyframes) = &mut signal.keyframes {
+                keyframes.sort_by(|a, b| a.0.total_cmp(&b.0));
+            }
+        }
+
         // Initialize state for each signal
         for (key, signal) in &config {
             let initial_state = SignalState {
@@ -37,16 +49,20 @@ impl SignalGenerator {
         Self {
             config,
             signal_state,
+            started: Instant::now(),
         }
     }
 
     // Function to get the next value of a signal
     pub fn get_next_signal_value(&mut self, signal_name: &str) -> Option<f64> {
+        // Milliseconds since the generator was created
+        let elapsed = self.started.elapsed().as_secs_f64() * 1000.0;
         if let Some(signal) = self.config.get(signal_name) {
             let state = self.signal_state.get_mut(signal_name)?;
 
             match signal.data_type {
-                DataType::Static => {
+                // Time series are resampled to static data when loading the config
+                DataType::Static | DataType::TimeSeries => {
                     if let Some(data) = &signal.data {
                         // Return the current value and advance the index
                         let value = data.get(state.current_index).copied();
@@ -90,6 +106,61 @@ impl SignalGenerator {
                         return value;
                     }
                 }
+                DataType::Sine | DataType::Square | DataType::Triangle => {
+                    let period = signal.period.filter(|period| *period > 0)? as f64;
+                    // Position within the current period, from 0 to 1
+                    let position =
+                        (elapsed / period + signal.phase.unwrap_or(0.0) / 360.0).rem_euclid(1.0);
+                    let shape = match signal.data_type {
+                        DataType::Sine => (TAU * position).sin(),
+                        DataType::Square if position < signal.duty_cycle.unwrap_or(0.5) => 1.0,
+                        DataType::Square => -1.0,
+                        // A triangle in phase with the sine
+                        _ => (TAU * position).sin().asin() * 2.0 / PI,
+                    };
+                    return Some(
+                        signal.offset.unwrap_or(0.0) + signal.amplitude.unwrap_or(1.0) * shape,
+                    );
+                }
+                DataType::RandomWalk => {
+                    let value = bounded(state.current_step, signal);
+                    let step_size = signal.step_size.unwrap_or(1.0).abs();
+                    let step = rand::thread_rng().gen_range(-step_size..=step_size);
+                    state.current_step = bounded(value + step, signal);
+                    return Some(value);
+                }
+                DataType::Gaussian => {
+                    let normal =
+                        Normal::new(signal.mean.unwrap_or(0.0), signal.std_dev.unwrap_or(1.0))
+                            .ok()?;
+                    return Some(bounded(normal.sample(&mut rand::thread_rng()), signal));
+                }
+                DataType::Step => {
+                    return if elapsed < signal.at.unwrap_or(0) as f64 {
+                        signal.start_value
+                    } else {
+                        signal.end_value
+                    };
+                }
+                DataType::Pulse => {
+                    let mut since = elapsed - signal.at.unwrap_or(0) as f64;
+                    if let Some(period) = signal.period.filter(|period| *period > 0) {
+                        if since >= 0.0 {
+                            since %= period as f64;
+                        }
+                    }
+                    return if since >= 0.0 && since < signal.width.unwrap_or(0) as f64 {
+                        signal.end_value
+                    } else {
+                        signal.start_value
+                    };
+                }
+                DataType::Keyframes => {
+                    let keyframes = signal.keyframes.as_ref().filter(|k| !k.is_empty())?;
+                    let last = keyframes[keyframes.len() - 1].0;
+                    let time = if last > 0.0 { elapsed % last } else { 0.0 };
+                    return Some(interpolate(keyframes, time));
+                }
                 _ => (),
             }
         }
@@ -125,6 +196,12 @@ impl SignalGenerator {
     }
 }
 
+// Keeps a value within the bounds of its signal, if it has any
+fn bounded(value: f64, signal: &SignalConfig) -> f64 {
+    let value = signal.min_value.map_or(value, |min| value.max(min));
+    signal.max_value.map_or(value, |max| value.min(max))
+}
+
 #[macro_export]
 macro_rules! define_generator {
     ($name:ident $(, $field_name:ident : $field_type:ty)*) => {
@@ -2,7 +2,11 @@
 pub mod config;
 pub mod generators;
 pub mod msg_generators;
+pub mod replay;
+pub mod route;
+pub mod scenario;
 pub mod task_spawner;
+pub mod time_series;
 
 pub use config::{RootConfig, SignalMockerServiceConfig};
 pub use generators::MessageGenerator;
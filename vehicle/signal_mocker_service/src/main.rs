@@ -1,14 +1,16 @@
 // This code was developed by OpenTier GmbH.
 use clap::Parser;
 use common::topics::*;
-use env_logger;
-use json5;
+use common::ZenohSessionConfig;
 use log::info;
 use signal_mocker_service::config::SignalOrNestedMessage;
 use signal_mocker_service::msg_generators::*;
+use signal_mocker_service::replay::Replay;
+use signal_mocker_service::route::RoutePlayback;
+use signal_mocker_service::scenario::{spawn_simulation, ScenarioGenerator, VehicleModel};
 use signal_mocker_service::{PublicationTaskSpawner, RootConfig};
 use std::fs;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 #[macro_export]
@@ -52,6 +54,10 @@ struct Args {
     // Path to the JSON5 configuration file
     #[arg(short, long)]
     config: String,
+
+    // Zenoh session options, overriding the ones from the configuration file
+    #[command(flatten)]
+    zenoh: ZenohSessionConfig,
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 2)]
@@ -60,58 +66,134 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let args = Args::parse();
 
     // Read the JSON5 configuration file
-    let config_str = fs::read_to_string(args.config)?;
+    let config_str = fs::read_to_string(&args.config)?;
 
     // Parse the JSON5 into our Rust structs
-    let config: RootConfig = json5::from_str(&config_str)?;
+    let mut config: RootConfig = json5::from_str(&config_str)?;
 
     // Initialize logger
     env_logger::init();
 
-    // create a zenoh session in peer mode
-    let zenoh_config = zenoh::Config::default();
-    let zenoh_session = Arc::new(zenoh::open(zenoh_config).await.unwrap());
+    config.signal_mocker_service.load_time_series()?;
+    config.signal_mocker_service.validate_signals()?;
+
+    // create a zenoh session
+    let zenoh_session = config
+        .signal_mocker_service
+        .zenoh
+        .clone()
+        .merge(args.zenoh)
+        .open()
+        .await?;
+
+    // Replay mode only republishes the recording
+    if let Some(replay) = &config.signal_mocker_service.replay {
+        let replay = Replay::from_config(replay)?;
+        info!(
+            "Replaying {} samples recorded over {:?}",
+            replay.sample_count(),
+            replay.duration()
+        );
+        replay.spawn(zenoh_session).await?;
+        return Ok(());
+    }
 
     // spawn publication tasks
-    let battery_data_pub_task = spawn_generator_task!(
-        BatteryDataGenerator,
-        "BatteryData",
-        BATTERY_STATE_TOPIC,
-        config,
-        zenoh_session.clone()
-    );
-
-    let exterior_pub_task = spawn_generator_task!(
+    let mut tasks = Vec::new();
+    match &config.signal_mocker_service.scenario {
+        // Correlated signals driven by the vehicle model of the scenario
+        Some(scenario) => {
+            let model = Arc::new(Mutex::new(VehicleModel::new(scenario)?));
+            tasks.push(spawn_simulation(
+                model.clone(),
+                Duration::from_millis(scenario.tick),
+            ));
+            // Scenario messages are published every tick unless configured otherwise
+            let frequency = |name: &str| {
+                Duration::from_millis(
+                    config
+                        .signal_mocker_service
+                        .messages
+                        .get(name)
+                        .map_or(scenario.tick, |message| message.frequency),
+                )
+            };
+            tasks.push(PublicationTaskSpawner::spawn_task(
+                zenoh_session.clone(),
+                BATTERY_STATE,
+                ScenarioGenerator::new(model.clone(), VehicleModel::battery_data),
+                frequency("BatteryData"),
+            ));
+            tasks.push(PublicationTaskSpawner::spawn_task(
+                zenoh_session.clone(),
+                SPEED,
+                ScenarioGenerator::new(model.clone(), VehicleModel::speed),
+                frequency("Speed"),
+            ));
+            tasks.push(PublicationTaskSpawner::spawn_task(
+                zenoh_session.clone(),
+                TRIP_DATA,
+                ScenarioGenerator::new(model.clone(), VehicleModel::trip_data),
+                frequency("TripData"),
+            ));
+            tasks.push(PublicationTaskSpawner::spawn_task(
+                zenoh_session.clone(),
+                CURRENT_LOCATION,
+                ScenarioGenerator::new(model, VehicleModel::current_location),
+                frequency("CurrentLocation"),
+            ));
+            info!("Driving scenario with {} phases", scenario.phases.len());
+        }
+        // Independent signals, each generated from its own config
+        None => {
+            tasks.push(spawn_generator_task!(
+                BatteryDataGenerator,
+                "BatteryData",
+                BATTERY_STATE,
+                config,
+                zenoh_session.clone()
+            ));
+
+            tasks.push(spawn_generator_task!(
+                SpeedGenerator,
+                "Speed",
+                SPEED,
+                config,
+                zenoh_session.clone()
+            ));
+
+            tasks.push(spawn_generator_task!(
+                TripDataGenerator,
+                "TripData",
+                TRIP_DATA,
+                config,
+                zenoh_session.clone()
+            ));
+
+            // Replay the configured route, if any
+            let playback = config.signal_mocker_service.messages["CurrentLocation"]
+                .route
+                .as_ref()
+                .map(RoutePlayback::from_config)
+                .transpose()?;
+            tasks.push(spawn_generator_task!(
+                CurrentLocationGenerator,
+                "CurrentLocation",
+                CURRENT_LOCATION,
+                config,
+                zenoh_session.clone(),
+                playback
+            ));
+        }
+    }
+
+    tasks.push(spawn_generator_task!(
         ExteriorGenerator,
         "Exterior",
-        EXTERIOR_TOPIC,
-        config,
-        zenoh_session.clone()
-    );
-
-    let speed_pub_task = spawn_generator_task!(
-        SpeedGenerator,
-        "Speed",
-        SPEED_TOPIC,
+        EXTERIOR,
         config,
         zenoh_session.clone()
-    );
-
-    let trip_data_pub_task = spawn_generator_task!(
-        TripDataGenerator,
-        "TripData",
-        TRIP_DATA_TOPIC,
-        config,
-        zenoh_session.clone()
-    );
-
-    let current_location_pub_task = spawn_generator_task!(
-        CurrentLocationGenerator,
-        "CurrentLocation",
-        CURRENT_LOCATION_TOPIC,
-        config,
-        zenoh_session.clone()
-    );
+    ));
 
     // Extract the nested signals for "FrontTire"
     let front_tire_signals = match config.signal_mocker_service.messages["Tires"]
@@ -125,7 +207,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     // Create the nested generator for "FrontTire"
     let front_tire_generator = TirePressureGenerator::new(
-        signal_mocker_service::config::extract_signals(&front_tire_signals),
+        signal_mocker_service::config::extract_signals(front_tire_signals),
     );
 
     // Extract the signals for "RearTire"
@@ -140,31 +222,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     // Create the nested generator for "FrontTire"
     let rear_tire_generator = TirePressureGenerator::new(
-        signal_mocker_service::config::extract_signals(&rear_tire_signals),
+        signal_mocker_service::config::extract_signals(rear_tire_signals),
     );
 
     // Spawn the TiresGenerator task
-    let tires_data_pub_task = spawn_generator_task!(
+    tasks.push(spawn_generator_task!(
         TiresGenerator,
-        "TripData",
-        TRIP_DATA_TOPIC,
+        "Tires",
+        TIRES,
         config,
         zenoh_session.clone(),
         front_tire_generator,
         rear_tire_generator
-    );
+    ));
 
     info!("Signal Mocker Service started");
 
     // wait for tasks to finish
-    tokio::try_join!(
-        battery_data_pub_task,
-        exterior_pub_task,
-        speed_pub_task,
-        trip_data_pub_task,
-        current_location_pub_task,
-        tires_data_pub_task
-    )?;
+    for task in tasks {
+        task.await?;
+    }
 
     Ok(())
 }
@@ -2,6 +2,7 @@
 use crate::config::*;
 use crate::define_generator;
 use crate::generators::{MessageGenerator, SignalGenerator};
+use crate::route::RoutePlayback;
 use chrono::Local;
 use std::collections::HashMap;
 use vehicle_msgs::battery::BatteryData;
@@ -181,9 +182,24 @@ impl MessageGenerator<TripData> for TripDataGenerator {
 }
 
 // CurrentLocationGenerator
-define_generator!(CurrentLocationGenerator);
+define_generator!(CurrentLocationGenerator, playback: Option<RoutePlayback>);
 impl MessageGenerator<CurrentLocation> for CurrentLocationGenerator {
     fn generate(&mut self) -> CurrentLocation {
+        let timestamp = Local::now().to_rfc3339();
+
+        // Replay the route at the Speed signal, if any
+        if let Some(playback) = &mut self.playback {
+            let speed = self.signal_generator.get_next_signal_value("Speed");
+            let position = playback.step(speed);
+            return CurrentLocation {
+                altitude: position.point.altitude,
+                latitude: position.point.latitude,
+                longitude: position.point.longitude,
+                timestamp,
+                heading: position.heading,
+            };
+        }
+
         let altitude = self
             .signal_generator
             .get_next_signal_value("Altitude")
@@ -199,13 +215,17 @@ impl MessageGenerator<CurrentLocation> for CurrentLocationGenerator {
             .get_next_signal_value("Longitude")
             .unwrap_or(0.0);
 
-        let timestamp = Local::now().to_rfc3339();
+        let heading = self
+            .signal_generator
+            .get_next_signal_value("Heading")
+            .unwrap_or(0.0);
 
         CurrentLocation {
             altitude,
             latitude,
             longitude,
             timestamp,
+            heading,
         }
     }
 }
@@ -0,0 +1,97 @@
+// This code was developed by OpenTier GmbH.
+use crate::config::ReplayConfig;
+use log::{error, info};
+use std::fs;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Duration, Instant};
+use vehicle_msgs::recording::read_recording;
+use vehicle_msgs::vehicle_recording::RecordedSample;
+use zenoh::bytes::Encoding;
+use zenoh::key_expr::KeyExpr;
+use zenoh::session::Session;
+
+// Republishes recorded samples with their original timing
+pub struct Replay {
+    samples: Vec<RecordedSample>,
+    speed: f64,
+    repeat: bool,
+}
+
+impl Replay {
+    pub fn from_config(
+        config: &ReplayConfig,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if config.speed <= 0.0 {
+            return Err(format!("Replay speed must be positive, got {}", config.speed).into());
+        }
+        let data = fs::read(&config.file)
+            .map_err(|e| format!("Failed to read recording {}: {}", config.file, e))?;
+        let filters = config
+            .topics
+            .iter()
+            .map(|topic| KeyExpr::try_from(topic.as_str()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut samples = Vec::new();
+        for sample in read_recording(&data)? {
+            let key_expr = KeyExpr::try_from(sample.key_expr.as_str())?;
+            if filters.is_empty() || filters.iter().any(|filter| filter.intersects(&key_expr)) {
+                samples.push(sample);
+            }
+        }
+        if samples.is_empty() {
+            return Err(format!("No samples to replay in {}", config.file).into());
+        }
+        // Samples of different subscribers may have been written out of order
+        samples.sort_by_key(|sample| sample.timestamp_us);
+        Ok(Self {
+            samples,
+            speed: config.speed,
+            repeat: config.repeat,
+        })
+    }
+
+    // Recorded time from the first to the last sample
+    pub fn duration(&self) -> Duration {
+        let first = self.samples.first().map_or(0, |sample| sample.timestamp_us);
+        let last = self.samples.last().map_or(0, |sample| sample.timestamp_us);
+        Duration::from_micros(last - first)
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn spawn(self, session: Arc<Session>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                self.play(&session).await;
+                if !self.repeat {
+                    info!("Replay finished");
+                    return;
+                }
+                info!("Replay finished, starting over");
+            }
+        })
+    }
+
+    async fn play(&self, session: &Session) {
+        let start = Instant::now();
+        let first = self.samples[0].timestamp_us;
+        for sample in &self.samples {
+            let offset = Duration::from_micros(sample.timestamp_us - first);
+            sleep_until(start + offset.div_f64(self.speed)).await;
+
+            let mut put = session
+                .put(sample.key_expr.clone(), sample.payload.clone())
+                .encoding(Encoding::from(sample.encoding.clone()));
+            if !sample.attachment.is_empty() {
+                put = put.attachment(sample.attachment.clone());
+            }
+            if let Err(e) = put.await {
+                error!("Failed to replay sample on '{}': {:?}", sample.key_expr, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,274 @@
+// This code was developed by OpenTier GmbH.
+use crate::config::{RouteConfig, RouteEnd, RoutePoint};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use tokio::time::Instant;
+
+const EARTH_RADIUS: f64 = 6_371_000.0; // in meters
+
+// Great-circle distance between two points in meters
+pub fn haversine(from: &RoutePoint, to: &RoutePoint) -> f64 {
+    let (lat1, lat2) = (from.latitude.to_radians(), to.latitude.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (to.longitude - from.longitude).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+// Initial bearing from one point to another in degrees clockwise from north
+pub fn bearing(from: &RoutePoint, to: &RoutePoint) -> f64 {
+    let (lat1, lat2) = (from.latitude.to_radians(), to.latitude.to_radians());
+    let d_lon = (to.longitude - from.longitude).to_radians();
+    let y = d_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+// A point on a route and the direction the vehicle faces there
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoutePosition {
+    pub point: RoutePoint,
+    pub heading: f64, // in degrees clockwise from north
+}
+
+// A polyline of waypoints, addressed by the distance driven along it
+#[derive(Debug, Clone)]
+pub struct Route {
+    points: Vec<RoutePoint>,
+    // Distance from the first point to each point in meters
+    distances: Vec<f64>,
+    end: RouteEnd,
+}
+
+impl Route {
+    pub fn new(points: Vec<RoutePoint>, end: RouteEnd) -> Result<Self, String> {
+        if points.is_empty() {
+            return Err("A route needs at least one point".to_string());
+        }
+        let mut distances = Vec::with_capacity(points.len());
+        let mut total = 0.0;
+        distances.push(total);
+        for pair in points.windows(2) {
+            total += haversine(&pair[0], &pair[1]);
+            distances.push(total);
+        }
+        Ok(Self {
+            points,
+            distances,
+            end,
+        })
+    }
+
+    // Route from the configured file, or from the configured points
+    pub fn from_config(config: &RouteConfig) -> Result<Self, String> {
+        let points = match &config.file {
+            Some(file) => load_route_file(Path::new(file))?,
+            None => config.points.clone(),
+        };
+        Self::new(points, config.end)
+    }
+
+    // Length of the route in meters
+    pub fn length(&self) -> f64 {
+        self.distances.last().copied().unwrap_or(0.0)
+    }
+
+    // Position after driving `driven` meters from the start, following the
+    // end behavior of the route once its length is exceeded
+    pub fn locate(&self, driven: f64) -> RoutePosition {
+        let length = self.length();
+        if length <= 0.0 {
+            return self.position_at(0.0, false);
+        }
+        let driven = driven.max(0.0);
+        match self.end {
+            RouteEnd::Loop => self.position_at(driven % length, false),
+            RouteEnd::Stop => self.position_at(driven.min(length), false),
+            RouteEnd::PingPong => {
+                let lap = driven % (2.0 * length);
+                if lap <= length {
+                    self.position_at(lap, false)
+                } else {
+                    self.position_at(2.0 * length - lap, true)
+                }
+            }
+        }
+    }
+
+    // Position at `distance` meters from the start, facing back to the start
+    // when driving the route in `reverse`
+    fn position_at(&self, distance: f64, reverse: bool) -> RoutePosition {
+        if self.points.len() < 2 {
+            return RoutePosition {
+                point: self.points[0],
+                heading: 0.0,
+            };
+        }
+        let distance = distance.clamp(0.0, self.length());
+        let next = self
+            .distances
+            .partition_point(|d| *d < distance)
+            .clamp(1, self.points.len() - 1);
+        let (from, to) = (&self.points[next - 1], &self.points[next]);
+        let segment = self.distances[next] - self.distances[next - 1];
+        let t = if segment > 0.0 {
+            (distance - self.distances[next - 1]) / segment
+        } else {
+            0.0
+        };
+        let heading = bearing(from, to);
+        RoutePosition {
+            point: RoutePoint {
+                latitude: from.latitude + (to.latitude - from.latitude) * t,
+                longitude: from.longitude + (to.longitude - from.longitude) * t,
+                altitude: from.altitude + (to.altitude - from.altitude) * t,
+            },
+            heading: if reverse {
+                (heading + 180.0) % 360.0
+            } else {
+                heading
+            },
+        }
+    }
+}
+
+// Replays a route in real time at the speed given on every step
+pub struct RoutePlayback {
+    route: Route,
+    // Speed used when no other is given, in km/h
+    speed: f64,
+    driven: f64, // in meters
+    last_step: Option<Instant>,
+}
+
+impl RoutePlayback {
+    pub fn from_config(config: &RouteConfig) -> Result<Self, String> {
+        Ok(Self {
+            route: Route::from_config(config)?,
+            speed: config.speed,
+            driven: 0.0,
+            last_step: None,
+        })
+    }
+
+    // Drives at `speed` km/h, or the configured speed, for the time passed
+    // since the previous step
+    pub fn step(&mut self, speed: Option<f64>) -> RoutePosition {
+        let now = Instant::now();
+        if let Some(last_step) = self.last_step {
+            let speed = speed.unwrap_or(self.speed).max(0.0);
+            self.driven += speed / 3.6 * (now - last_step).as_secs_f64();
+        }
+        self.last_step = Some(now);
+        self.route.locate(self.driven)
+    }
+}
+
+// Points of a GPX track or route, or of a GeoJSON LineString
+fn load_route_file(path: &Path) -> Result<Vec<RoutePoint>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read route file {}: {}", path.display(), e))?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+    let points = match extension.as_deref() {
+        Some("gpx") => parse_gpx(&content),
+        Some("geojson") | Some("json") => parse_geojson(&content),
+        _ => Err("expected a .gpx, .geojson or .json file".to_string()),
+    }
+    .map_err(|e| format!("Invalid route file {}: {}", path.display(), e))?;
+    if points.is_empty() {
+        return Err(format!("Route file {} has no points", path.display()));
+    }
+    Ok(points)
+}
+
+// Track points, or route points if there is no track, in document order
+fn parse_gpx(content: &str) -> Result<Vec<RoutePoint>, String> {
+    let document = roxmltree::Document::parse(content).map_err(|e| e.to_string())?;
+    let points_named = |name: &str| -> Result<Vec<RoutePoint>, String> {
+        document
+            .descendants()
+            .filter(|node| node.has_tag_name(name))
+            .map(|node| {
+                let coordinate = |attribute: &str| {
+                    node.attribute(attribute)
+                        .and_then(|value| value.trim().parse::<f64>().ok())
+                        .ok_or_else(|| format!("<{}> without a valid {}", name, attribute))
+                };
+                let altitude = node
+                    .children()
+                    .find(|child| child.has_tag_name("ele"))
+                    .and_then(|ele| ele.text())
+                    .and_then(|text| text.trim().parse().ok())
+                    .unwrap_or(0.0);
+                Ok(RoutePoint {
+                    latitude: coordinate("lat")?,
+                    longitude: coordinate("lon")?,
+                    altitude,
+                })
+            })
+            .collect()
+    };
+    let track = points_named("trkpt")?;
+    if track.is_empty() {
+        points_named("rtept")
+    } else {
+        Ok(track)
+    }
+}
+
+// The first LineString or MultiLineString of a geometry, feature or
+// feature collection
+fn parse_geojson(content: &str) -> Result<Vec<RoutePoint>, String> {
+    let root: Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let geometry = match root["type"].as_str() {
+        Some("FeatureCollection") => root["features"]
+            .as_array()
+            .and_then(|features| {
+                features
+                    .iter()
+                    .map(|feature| &feature["geometry"])
+                    .find(|geometry| {
+                        matches!(
+                            geometry["type"].as_str(),
+                            Some("LineString") | Some("MultiLineString")
+                        )
+                    })
+            })
+            .ok_or("no LineString feature")?,
+        Some("Feature") => &root["geometry"],
+        _ => &root,
+    };
+    let coordinates = &geometry["coordinates"];
+    let lines: Vec<&Value> = match geometry["type"].as_str() {
+        Some("LineString") => vec![coordinates],
+        Some("MultiLineString") => coordinates
+            .as_array()
+            .ok_or("MultiLineString without coordinates")?
+            .iter()
+            .collect(),
+        other => return Err(format!("expected a LineString, got {:?}", other)),
+    };
+    let mut points = Vec::new();
+    for line in lines {
+        for position in line.as_array().ok_or("LineString without coordinates")? {
+            // GeoJSON positions are [longitude, latitude, altitude]
+            let values: Vec<f64> = position
+                .as_array()
+                .map(|values| values.iter().filter_map(Value::as_f64).collect())
+                .unwrap_or_default();
+            if values.len() < 2 {
+                return Err(format!("invalid position {}", position));
+            }
+            points.push(RoutePoint {
+                latitude: values[1],
+                longitude: values[0],
+                altitude: values.get(2).copied().unwrap_or(0.0),
+            });
+        }
+    }
+    Ok(points)
+}
@@ -0,0 +1,295 @@
+// This code was developed by OpenTier GmbH.
+use crate::config::{DrivePhase, ScenarioConfig, VehicleModelConfig};
+use crate::generators::MessageGenerator;
+use crate::route::Route;
+use chrono::Local;
+use log::{info, warn};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Duration};
+use vehicle_msgs::battery::BatteryData;
+use vehicle_msgs::current_location::CurrentLocation;
+use vehicle_msgs::speed::Speed;
+use vehicle_msgs::trip_data::TripData;
+
+pub type SharedVehicleModel = Arc<Mutex<VehicleModel>>;
+
+// Braking after the last phase of a scenario that does not repeat, in m/s²
+const FINAL_DECELERATION: f64 = 3.0;
+
+// Kinematics and energy of a vehicle driving a scenario, so that speed,
+// distance, battery and location stay consistent with each other
+#[derive(Debug)]
+pub struct VehicleModel {
+    vehicle: VehicleModelConfig,
+    phases: Vec<DrivePhase>,
+    repeat: bool,
+    route: Route,
+    phase: usize,
+    // Time spent in the current phase, and standing still in it, in seconds
+    phase_elapsed: f64,
+    standstill: f64,
+    finished: bool,
+    speed: f64,          // in km/h
+    energy: f64,         // in kWh
+    charge_power: f64,   // in kW, 0 when not charging
+    trip_distance: f64,  // in km
+    trip_duration: f64,  // in seconds
+    route_distance: f64, // in meters along the route
+    start_time: String,
+}
+
+impl VehicleModel {
+    pub fn new(scenario: &ScenarioConfig) -> Result<Self, String> {
+        if scenario.phases.is_empty() {
+            return Err("A scenario needs at least one phase".to_string());
+        }
+        let vehicle = scenario.vehicle.clone();
+        if vehicle.battery_capacity <= 0.0 {
+            return Err("The battery capacity must be positive".to_string());
+        }
+        for (index, phase) in scenario.phases.iter().enumerate() {
+            let (name, value) = match phase {
+                DrivePhase::Accelerate { acceleration, .. } => ("acceleration", *acceleration),
+                DrivePhase::Stop { deceleration, .. } => ("deceleration", *deceleration),
+                DrivePhase::Charge { power, .. } => ("power", *power),
+                DrivePhase::Cruise { .. } => continue,
+            };
+            if value <= 0.0 {
+                return Err(format!(
+                    "Scenario phase {}: the {} must be positive",
+                    index, name
+                ));
+            }
+        }
+        Ok(Self {
+            energy: vehicle.battery_capacity * vehicle.initial_battery_level.clamp(0.0, 100.0)
+                / 100.0,
+            vehicle,
+            phases: scenario.phases.clone(),
+            repeat: scenario.repeat,
+            route: Route::from_config(&scenario.route)?,
+            phase: 0,
+            phase_elapsed: 0.0,
+            standstill: 0.0,
+            finished: false,
+            speed: 0.0,
+            charge_power: 0.0,
+            trip_distance: 0.0,
+            trip_duration: 0.0,
+            route_distance: 0.0,
+            start_time: Local::now().to_rfc3339(),
+        })
+    }
+
+    // Advances the simulation by `dt` seconds
+    pub fn step(&mut self, dt: f64) {
+        let previous_speed = self.speed;
+        self.charge_power = 0.0;
+        self.phase_elapsed += dt;
+
+        let mut done = match self.phases.get(self.phase).cloned() {
+            _ if self.finished => {
+                self.brake(FINAL_DECELERATION, dt);
+                false
+            }
+            Some(DrivePhase::Accelerate {
+                speed,
+                acceleration,
+            }) => {
+                let change = acceleration * 3.6 * dt;
+                self.speed = if self.speed < speed {
+                    (self.speed + change).min(speed)
+                } else {
+                    (self.speed - change).max(speed.max(0.0))
+                };
+                self.speed == speed.max(0.0)
+            }
+            Some(DrivePhase::Cruise { duration }) => self.phase_elapsed * 1000.0 >= duration as f64,
+            Some(DrivePhase::Stop {
+                deceleration,
+                duration,
+            }) => {
+                self.brake(deceleration, dt);
+                if self.speed == 0.0 {
+                    self.standstill += dt;
+                }
+                self.speed == 0.0 && self.standstill * 1000.0 >= duration as f64
+            }
+            Some(DrivePhase::Charge {
+                power,
+                battery_level,
+            }) => {
+                self.speed = 0.0;
+                let target =
+                    self.vehicle.battery_capacity * battery_level.clamp(0.0, 100.0) / 100.0;
+                if self.energy < target {
+                    self.charge_power = power;
+                    self.energy = (self.energy + power * dt / 3600.0).min(target);
+                }
+                self.energy >= target
+            }
+            None => true,
+        };
+
+        // Distance of this step from the mean speed, in km
+        let distance = (previous_speed + self.speed) / 2.0 * dt / 3600.0;
+        self.trip_distance += distance;
+        self.trip_duration += dt;
+        self.route_distance += distance * 1000.0;
+        self.energy -= distance * self.vehicle.consumption / 100.0;
+        if self.energy <= 0.0 {
+            self.energy = 0.0;
+            if self.speed > 0.0 {
+                self.battery_empty();
+                // The phase already changed, the charge phase must not end
+                done = false;
+            }
+        }
+
+        if done {
+            self.next_phase();
+        }
+    }
+
+    fn brake(&mut self, deceleration: f64, dt: f64) {
+        self.speed = (self.speed - deceleration * 3.6 * dt).max(0.0);
+    }
+
+    fn next_phase(&mut self) {
+        self.phase += 1;
+        self.phase_elapsed = 0.0;
+        self.standstill = 0.0;
+        if self.phase >= self.phases.len() {
+            if self.repeat {
+                self.phase = 0;
+            } else {
+                info!("Scenario finished, the vehicle comes to a stop");
+                self.finished = true;
+                return;
+            }
+        }
+        info!(
+            "Scenario phase {}: {:?}",
+            self.phase, self.phases[self.phase]
+        );
+    }
+
+    // An empty battery stops the vehicle, which continues with the next
+    // charge phase if there is one
+    fn battery_empty(&mut self) {
+        self.speed = 0.0;
+        let charge = (1..=self.phases.len())
+            .map(|offset| (self.phase + offset) % self.phases.len())
+            .find(|phase| matches!(self.phases[*phase], DrivePhase::Charge { .. }));
+        match charge {
+            Some(phase) => {
+                warn!("Battery empty, skipping to charge phase {}", phase);
+                self.phase = phase;
+                self.phase_elapsed = 0.0;
+                self.standstill = 0.0;
+            }
+            None => {
+                warn!("Battery empty and no charge phase, the vehicle stays stopped");
+                self.finished = true;
+            }
+        }
+    }
+
+    fn battery_level(&self) -> f64 {
+        self.energy / self.vehicle.battery_capacity * 100.0
+    }
+
+    pub fn speed(&self) -> Speed {
+        Speed {
+            value: self.speed as f32,
+        }
+    }
+
+    pub fn trip_data(&self) -> TripData {
+        let average_speed = if self.trip_duration > 0.0 {
+            self.trip_distance / (self.trip_duration / 3600.0)
+        } else {
+            0.0
+        };
+        TripData {
+            traveled_distance: (self.vehicle.odometer + self.trip_distance) as f32,
+            traveled_distance_since_start: self.trip_distance as f32,
+            trip_duration: self.trip_duration as f32,
+            trip_meter_reading: self.trip_distance as f32,
+            average_speed: average_speed as f32,
+            start_time: self.start_time.clone(),
+        }
+    }
+
+    pub fn battery_data(&self) -> BatteryData {
+        let time_to_fully_charge = if self.charge_power > 0.0 {
+            (self.vehicle.battery_capacity - self.energy) / self.charge_power * 60.0
+        } else {
+            0.0
+        };
+        let estimated_range = if self.vehicle.consumption > 0.0 {
+            self.energy / self.vehicle.consumption * 100.0
+        } else {
+            0.0
+        };
+        BatteryData {
+            is_charging: self.charge_power > 0.0,
+            is_discharging: self.speed > 0.0,
+            time_to_fully_charge: time_to_fully_charge.round() as u32,
+            estimated_range: estimated_range.round() as u32,
+            battery_level: self.battery_level() as f32,
+            state_of_health: self.vehicle.state_of_health as f32,
+            temperature: self.vehicle.battery_temperature as f32,
+        }
+    }
+
+    pub fn current_location(&self) -> CurrentLocation {
+        let position = self.route.locate(self.route_distance);
+        CurrentLocation {
+            altitude: position.point.altitude,
+            latitude: position.point.latitude,
+            longitude: position.point.longitude,
+            timestamp: Local::now().to_rfc3339(),
+            heading: position.heading,
+        }
+    }
+}
+
+// A poisoned lock only means a generator panicked, the model stays usable
+fn lock(model: &SharedVehicleModel) -> MutexGuard<'_, VehicleModel> {
+    model.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Steps the shared model every `tick`
+pub fn spawn_simulation(model: SharedVehicleModel, tick: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = interval(tick);
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            lock(&model).step(tick.as_secs_f64());
+        }
+    })
+}
+
+// Generates a message from the current state of the shared model
+pub struct ScenarioGenerator<T> {
+    model: SharedVehicleModel,
+    build: fn(&VehicleModel) -> T,
+}
+
+impl<T> ScenarioGenerator<T> {
+    pub fn new(model: SharedVehicleModel, build: fn(&VehicleModel) -> T) -> Self {
+        Self { model, build }
+    }
+}
+
+impl<T> MessageGenerator<T> for ScenarioGenerator<T>
+where
+    T: prost::Message + Send + Sync + 'static,
+{
+    fn generate(&mut self) -> T {
+        (self.build)(&lock(&self.model))
+    }
+}
@@ -1,8 +1,8 @@
 // This code was developed by OpenTier GmbH.
 use crate::generators::MessageGenerator;
-use common::publishers::{DataPublisher, ZenohPublisher};
+use common::publishers::{DataPublisher, TopicPublisher};
+use common::{Topic, TopicMessage};
 use log::error;
-use prost::Message;
 use std::sync::Arc;
 use tokio::task::JoinHandle;
 use tokio::time::{sleep, Duration};
@@ -14,17 +14,17 @@ impl PublicationTaskSpawner {
     // Static method to spawn the task
     pub fn spawn_task<G, T>(
         session: Arc<Session>,
-        key_expr: &'static str,
+        topic: Topic<T>,
         mut generator: G,
         frequency: Duration,
     ) -> JoinHandle<()>
     where
         G: MessageGenerator<T> + Send + 'static,
-        T: Message + Send + Sync + 'static,
+        T: TopicMessage,
     {
         tokio::spawn(async move {
-            // Create ZenohPublisher asynchronously
-            match ZenohPublisher::new(session, key_expr).await {
+            // Create TopicPublisher asynchronously
+            match TopicPublisher::new(session, topic).await {
                 Ok(publisher) => {
                     loop {
                         // Generate the message
@@ -40,7 +40,7 @@ impl PublicationTaskSpawner {
                     }
                 }
                 Err(e) => {
-                    error!("Failed to create ZenohPublisher: {:?}", e);
+                    error!("Failed to create TopicPublisher: {:?}", e);
                 }
             }
         })
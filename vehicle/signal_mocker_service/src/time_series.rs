@@ -0,0 +1,218 @@
+// This code was developed by OpenTier GmbH.
+use chrono::{DateTime, NaiveDateTime};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+use std::fs::File;
+use std::path::Path;
+
+// Samples of a signal recorded over time, e.g. a drive cycle speed trace
+#[derive(Debug, Clone)]
+pub struct TimeSeries {
+    // (time in seconds, value), sorted by time
+    samples: Vec<(f64, f64)>,
+}
+
+impl TimeSeries {
+    // Loads a CSV or Parquet file. Columns are picked by name, or else the
+    // first one holds the timestamps and the second one the values
+    pub fn load(
+        file: &str,
+        timestamp_column: Option<&str>,
+        value_column: Option<&str>,
+    ) -> Result<Self, String> {
+        let path = Path::new(file);
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        let mut samples = match extension.as_deref() {
+            Some("csv") => read_csv(path, timestamp_column, value_column),
+            Some("parquet") => read_parquet(path, timestamp_column, value_column),
+            _ => Err("expected a .csv or .parquet file".to_string()),
+        }
+        .map_err(|e| format!("Invalid time series {}: {}", file, e))?;
+        if samples.is_empty() {
+            return Err(format!("Time series {} has no samples", file));
+        }
+        samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Self { samples })
+    }
+
+    // Value at `time` seconds, linearly interpolated between the samples
+    pub fn value_at(&self, time: f64) -> f64 {
+        interpolate(&self.samples, time)
+    }
+
+    // Values from the first to the last sample, one every `period` seconds
+    pub fn resample(&self, period: f64) -> Vec<f64> {
+        let start = self.samples[0].0;
+        let duration = self.samples[self.samples.len() - 1].0 - start;
+        if period <= 0.0 {
+            return vec![self.samples[0].1];
+        }
+        let count = (duration / period).floor() as usize + 1;
+        (0..count)
+            .map(|i| self.value_at(start + i as f64 * period))
+            .collect()
+    }
+}
+
+// Value at `time` on the polyline through (time, value) points sorted by
+// time, holding the first and last value outside of them
+pub fn interpolate(points: &[(f64, f64)], time: f64) -> f64 {
+    let next = points.partition_point(|(t, _)| *t < time);
+    if next == 0 {
+        return points.first().map_or(0.0, |(_, value)| *value);
+    }
+    if next == points.len() {
+        return points[next - 1].1;
+    }
+    let ((t0, v0), (t1, v1)) = (points[next - 1], points[next]);
+    if t1 > t0 {
+        v0 + (v1 - v0) * (time - t0) / (t1 - t0)
+    } else {
+        v1
+    }
+}
+
+// Numeric timestamps are in seconds, others RFC 3339 or `%Y-%m-%d %H:%M:%S`
+fn parse_timestamp(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if let Ok(seconds) = text.parse::<f64>() {
+        return Some(seconds);
+    }
+    let time = DateTime::parse_from_rfc3339(text)
+        .map(|time| time.to_utc())
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f").map(|t| t.and_utc())
+        })
+        .ok()?;
+    Some(time.timestamp_micros() as f64 / 1e6)
+}
+
+// Index of the named column, or the default one
+fn column_index(names: &[String], name: Option<&str>, default: usize) -> Result<usize, String> {
+    match name {
+        Some(name) => names
+            .iter()
+            .position(|column| column == name)
+            .ok_or_else(|| format!("no column '{}' in {:?}", name, names)),
+        None if default < names.len() => Ok(default),
+        None => Err(format!("expected at least {} columns", default + 1)),
+    }
+}
+
+fn read_csv(
+    path: &Path,
+    timestamp_column: Option<&str>,
+    value_column: Option<&str>,
+) -> Result<Vec<(f64, f64)>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| e.to_string())?;
+    let mut records = reader.records();
+    let first = match records.next() {
+        Some(record) => record.map_err(|e| e.to_string())?,
+        None => return Ok(Vec::new()),
+    };
+    // A header row does not start with a timestamp
+    let (names, first_row) = if parse_timestamp(&first[0]).is_some() {
+        let names = (0..first.len()).map(|i| i.to_string()).collect::<Vec<_>>();
+        (names, Some(first))
+    } else {
+        (first.iter().map(str::to_string).collect(), None)
+    };
+    if first_row.is_some() && (timestamp_column.is_some() || value_column.is_some()) {
+        return Err("columns can only be picked by name with a header row".to_string());
+    }
+    let timestamp = column_index(&names, timestamp_column, 0)?;
+    let value = column_index(&names, value_column, 1)?;
+
+    let mut samples = Vec::new();
+    for (row, record) in first_row.into_iter().map(Ok).chain(records).enumerate() {
+        let record = record.map_err(|e| e.to_string())?;
+        let (Some(time), Some(text)) = (record.get(timestamp), record.get(value)) else {
+            continue;
+        };
+        // Logs often have gaps in some of their columns
+        if text.is_empty() {
+            continue;
+        }
+        let time = parse_timestamp(time)
+            .ok_or_else(|| format!("invalid timestamp '{}' in row {}", time, row + 1))?;
+        let value = text
+            .parse::<f64>()
+            .map_err(|_| format!("invalid value '{}' in row {}", text, row + 1))?;
+        samples.push((time, value));
+    }
+    Ok(samples)
+}
+
+fn read_parquet(
+    path: &Path,
+    timestamp_column: Option<&str>,
+    value_column: Option<&str>,
+) -> Result<Vec<(f64, f64)>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let reader = SerializedFileReader::new(file).map_err(|e| e.to_string())?;
+    let names: Vec<String> = reader
+        .metadata()
+        .file_metadata()
+        .schema()
+        .get_fields()
+        .iter()
+        .map(|field| field.name().to_string())
+        .collect();
+    let timestamp = column_index(&names, timestamp_column, 0)?;
+    let value = column_index(&names, value_column, 1)?;
+
+    let mut samples = Vec::new();
+    let rows = reader.get_row_iter(None).map_err(|e| e.to_string())?;
+    for (index, row) in rows.enumerate() {
+        let row = row.map_err(|e| e.to_string())?;
+        let fields: Vec<&Field> = row.get_column_iter().map(|(_, field)| field).collect();
+        let (Some(time), Some(value)) = (fields.get(timestamp), fields.get(value)) else {
+            continue;
+        };
+        // Logs often have gaps in some of their columns
+        if matches!(value, Field::Null) {
+            continue;
+        }
+        let time = field_timestamp(time)
+            .ok_or_else(|| format!("invalid timestamp {} in row {}", time, index + 1))?;
+        let value = field_value(value)
+            .ok_or_else(|| format!("invalid value {} in row {}", value, index + 1))?;
+        samples.push((time, value));
+    }
+    Ok(samples)
+}
+
+fn field_timestamp(field: &Field) -> Option<f64> {
+    match field {
+        Field::TimestampMillis(millis) => Some(*millis as f64 / 1e3),
+        Field::TimestampMicros(micros) => Some(*micros as f64 / 1e6),
+        Field::Str(text) => parse_timestamp(text),
+        field => field_value(field),
+    }
+}
+
+fn field_value(field: &Field) -> Option<f64> {
+    match field {
+        Field::Bool(value) => Some(*value as u8 as f64),
+        Field::Byte(value) => Some(*value as f64),
+        Field::Short(value) => Some(*value as f64),
+        Field::Int(value) => Some(*value as f64),
+        Field::Long(value) => Some(*value as f64),
+        Field::UByte(value) => Some(*value as f64),
+        Field::UShort(value) => Some(*value as f64),
+        Field::UInt(value) => Some(*value as f64),
+        Field::ULong(value) => Some(*value as f64),
+        Field::Float(value) => Some(*value as f64),
+        Field::Double(value) => Some(*value),
+        Field::Str(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
@@ -1,392 +1,583 @@
+use crate::command_status::{generate_correlation_id, CommandReporter, CommandRequest};
+use crate::config::{Command, Event, PublishMode, PublishPolicy, StaleAction, TwinServiceConfig};
+use crate::publish_policy::PublishGate;
+use crate::supervisor::{Criticality, Supervisor};
+use crate::telemetry_buffer::{replay_buffered_events, TelemetryBuffer};
+use crate::uplink::{publish_batches, BatchPublication, Uplink};
 use crate::vehicle_state::{VehicleCommand, VehicleState};
+use common::topics::{
+    BATTERY_STATE_TOPIC, CURRENT_LOCATION_TOPIC, EXTERIOR_TOPIC, LOCK_STATE_TOPIC,
+    POWERTRAIN_TOPIC, SPEED_TOPIC, TIRES_TOPIC, TRIP_DATA_TOPIC,
+};
 use common::DataPublisher;
-use common::ZenohPublisher;
 use common::ZenohSubscriber;
+use common::{Topic, TopicMessage, TopicPublisher, WireFormat};
+use futures::future::BoxFuture;
+use futures::FutureExt;
 use log::{error, info, trace};
 use prost::Message;
+use std::fmt::Debug;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
-use tokio::task::JoinHandle;
+use vehicle_msgs::vehicle_command_status::{CommandStatus, CommandStatusEvent};
 use vehicle_msgs::vehicle_commands::*;
 
-const CLOUD_BATTERY_TOPIC: &str = "cloud/telemetry/battery_event";
-const CLOUD_TRIP_DATA_TOPIC: &str = "cloud/telemetry/trip_data";
-const CLOUD_SPEED_TOPIC: &str = "cloud/telemetry/speed";
-const CLOUD_EXTERIOR_TOPIC: &str = "cloud/telemetry/exterior";
-const CLOUD_TIRES_TOPIC: &str = "cloud/telemetry/tires";
-const CLOUD_LOCK_STATE_TOPIC: &str = "cloud/telemetry/system_state";
-const CLOUD_LOCATION_TOPIC: &str = "cloud/telemetry/location";
+// Cloud events the twin knows how to build from the vehicle state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloudEvent {
+    Battery,
+    Speed,
+    CurrentLocation,
+    Exterior,
+    Tires,
+    SystemState,
+    TripData,
+}
+
+impl CloudEvent {
+    pub const ALL: [CloudEvent; 7] = [
+        CloudEvent::Battery,
+        CloudEvent::Speed,
+        CloudEvent::CurrentLocation,
+        CloudEvent::Exterior,
+        CloudEvent::Tires,
+        CloudEvent::SystemState,
+        CloudEvent::TripData,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CloudEvent::Battery => "Battery",
+            CloudEvent::Speed => "Speed",
+            CloudEvent::CurrentLocation => "CurrentLocation",
+            CloudEvent::Exterior => "Exterior",
+            CloudEvent::Tires => "Tires",
+            CloudEvent::SystemState => "SystemState",
+            CloudEvent::TripData => "TripData",
+        }
+    }
+
+    // Intra-vehicle signals the event is built from
+    pub fn signals(&self) -> &'static [&'static str] {
+        match self {
+            CloudEvent::Battery => &[BATTERY_STATE_TOPIC],
+            CloudEvent::Speed => &[SPEED_TOPIC],
+            CloudEvent::CurrentLocation => &[CURRENT_LOCATION_TOPIC],
+            CloudEvent::Exterior => &[EXTERIOR_TOPIC],
+            CloudEvent::Tires => &[TIRES_TOPIC],
+            CloudEvent::SystemState => &[LOCK_STATE_TOPIC, POWERTRAIN_TOPIC],
+            CloudEvent::TripData => &[TRIP_DATA_TOPIC],
+        }
+    }
+}
+
+impl FromStr for CloudEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CloudEvent::ALL
+            .into_iter()
+            .find(|event| event.name() == s)
+            .ok_or_else(|| {
+                let known: Vec<&str> = CloudEvent::ALL.iter().map(|e| e.name()).collect();
+                format!(
+                    "Unknown cloud event '{}' in twin config, expected one of: {}",
+                    s,
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+// A configured cloud event: what to publish, where, how often the state is
+// sampled and which samples actually get published
+#[derive(Debug, Clone)]
+pub struct EventPublication {
+    pub event: CloudEvent,
+    pub topic: String,
+    pub format: WireFormat,
+    pub frequency: Duration,
+    pub policy: PublishPolicy,
+    pub on_stale: StaleAction,
+    pub priority: u8,
+}
+
+impl TryFrom<&Event> for EventPublication {
+    type Error = String;
+
+    fn try_from(event: &Event) -> Result<Self, Self::Error> {
+        if event.frequency == 0 {
+            return Err(format!(
+                "Cloud event '{}' must have a non-zero frequency",
+                event.name
+            ));
+        }
+        if event.policy.mode == PublishMode::Periodic && event.policy.deadband.is_some() {
+            return Err(format!(
+                "Cloud event '{}' sets a deadband, which requires the 'on_change' mode",
+                event.name
+            ));
+        }
+        if let (Some(min), Some(max)) = (event.policy.min_interval, event.policy.max_interval) {
+            if min > max {
+                return Err(format!(
+                    "Cloud event '{}' has a min_interval greater than its max_interval",
+                    event.name
+                ));
+            }
+        }
+        Ok(Self {
+            event: event.name.parse()?,
+            topic: event.topic.clone(),
+            format: event.format,
+            frequency: Duration::from_millis(event.frequency),
+            policy: event.policy.clone(),
+            on_stale: event.on_stale,
+            priority: event.priority,
+        })
+    }
+}
+
+// Cloud commands the twin knows how to translate into vehicle commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloudCommand {
+    LockUnlock,
+    TurnOnOff,
+}
+
+impl CloudCommand {
+    pub const ALL: [CloudCommand; 2] = [CloudCommand::LockUnlock, CloudCommand::TurnOnOff];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CloudCommand::LockUnlock => "LockUnlock",
+            CloudCommand::TurnOnOff => "TurnOnOff",
+        }
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Result<VehicleCommand, prost::DecodeError> {
+        match self {
+            CloudCommand::LockUnlock => {
+                let message = LockUnlockCommand::decode(bytes)?;
+                Ok(if message.state == LockState::Lock as i32 {
+                    VehicleCommand::Lock
+                } else {
+                    VehicleCommand::Unlock
+                })
+            }
+            CloudCommand::TurnOnOff => {
+                let message = GeneralStateCommand::decode(bytes)?;
+                Ok(
+                    if message.state == CommandState::On as i32
+                        && message.target == CommandTarget::Lights as i32
+                    {
+                        VehicleCommand::LightOn
+                    } else if message.state == CommandState::Off as i32
+                        && message.target == CommandTarget::Lights as i32
+                    {
+                        VehicleCommand::LightOff
+                    } else if message.state == CommandState::On as i32
+                        && message.target == CommandTarget::Engine as i32
+                    {
+                        VehicleCommand::EngineOn
+                    } else if message.state == CommandState::Off as i32
+                        && message.target == CommandTarget::Engine as i32
+                    {
+                        VehicleCommand::EngineOff
+                    } else if message.state == CommandState::On as i32
+                        && message.target == CommandTarget::Horn as i32
+                    {
+                        VehicleCommand::HornOn
+                    } else {
+                        VehicleCommand::HornOff
+                    },
+                )
+            }
+        }
+    }
+}
+
+impl FromStr for CloudCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CloudCommand::ALL
+            .into_iter()
+            .find(|command| command.name() == s)
+            .ok_or_else(|| {
+                let known: Vec<&str> = CloudCommand::ALL.iter().map(|c| c.name()).collect();
+                format!(
+                    "Unknown cloud command '{}' in twin config, expected one of: {}",
+                    s,
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+// A configured cloud command and the resolved key expression it arrives on
+#[derive(Debug, Clone)]
+pub struct CommandSubscription {
+    pub command: CloudCommand,
+    pub key_expr: String,
+}
+
+impl CommandSubscription {
+    pub fn from_config(command: &Command, config: &TwinServiceConfig) -> Result<Self, String> {
+        Ok(Self {
+            command: command.name.parse()?,
+            key_expr: config.resolve_topic(&command.topic)?,
+        })
+    }
+}
 
 pub struct CloudCommunicator {
     state: Arc<Mutex<VehicleState>>,
+    publications: Vec<EventPublication>,
+    subscriptions: Vec<CommandSubscription>,
+    status_key_expr: String,
+    buffer: Option<Arc<TelemetryBuffer>>,
+    buffer_retry_interval: Duration,
+    batching: Option<BatchPublication>,
+    vehicle_id: String,
 }
 
 impl CloudCommunicator {
-    pub fn new(state: Arc<Mutex<VehicleState>>) -> Self {
-        Self { state }
+    pub async fn new(
+        state: Arc<Mutex<VehicleState>>,
+        config: &TwinServiceConfig,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let publications = config
+            .events
+            .iter()
+            .map(EventPublication::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        let subscriptions = config
+            .commands
+            .iter()
+            .map(|command| CommandSubscription::from_config(command, config))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            state,
+            publications,
+            subscriptions,
+            status_key_expr: config.resolve_topic(&config.command_status_topic)?,
+            buffer: match &config.buffer {
+                Some(buffer) => Some(Arc::new(TelemetryBuffer::open(buffer).await?)),
+                None => None,
+            },
+            buffer_retry_interval: Duration::from_millis(
+                config
+                    .buffer
+                    .as_ref()
+                    .map_or(0, |buffer| buffer.retry_interval),
+            ),
+            batching: config
+                .batching
+                .as_ref()
+                .map(|batching| BatchPublication::from_config(batching, config))
+                .transpose()?,
+            vehicle_id: config.vehicle_id.clone(),
+        })
     }
 
-    pub async fn run(
+    pub fn run(
         &self,
         session: Arc<zenoh::Session>,
-        command_tx: mpsc::Sender<VehicleCommand>,
-    ) -> Result<Vec<JoinHandle<()>>, Box<dyn std::error::Error + Send + Sync>> {
-        // Task to periodically publish vehicle state to the cloud
-        let lock_state_publisher = {
+        command_tx: mpsc::Sender<CommandRequest>,
+        reporter: CommandReporter,
+        status_rx: mpsc::Receiver<CommandStatusEvent>,
+        supervisor: &mut Supervisor,
+    ) {
+        // Task to publish the events in batches instead of on their own topics
+        let batch_tx = self.batching.as_ref().map(|batching| {
+            let (batch_tx, batch_rx) = Uplink::batch_channel();
+            supervisor.spawn_once(
+                "event batch publisher",
+                publish_batches(
+                    session.clone(),
+                    batching.clone(),
+                    self.vehicle_id.clone(),
+                    self.buffer.clone(),
+                    batch_rx,
+                ),
+            );
+            batch_tx
+        });
+        let uplink = Uplink::new(self.buffer.clone(), batch_tx);
+
+        // Tasks to periodically publish the configured events to the cloud
+        for publication in &self.publications {
             let state = Arc::clone(&self.state);
-            let session = Arc::clone(&session); // Cloning session for use inside async block
-            tokio::spawn(async move {
-                match ZenohPublisher::new(session.clone(), CLOUD_LOCK_STATE_TOPIC).await {
-                    Ok(publisher) => {
-                        loop {
-                            let event = {
-                                let vehicle_state = state.lock().await;
-                                vehicle_state.to_state_event()
-                            };
-                            // Publish vehicle state to the cloud
-                            if let Some(event) = event {
-                                trace!("Publishing lock state event to the cloud: {:?}", event);
-                                if let Err(e) = publisher.publish(event).await {
-                                    error!("Failed to publish speed: {:?}", e);
-                                }
-                            } else {
-                                error!("Failed to create speed event");
-                            }
+            let session = session.clone();
+            let publication = publication.clone();
+            let uplink = uplink.clone();
+            supervisor.spawn(
+                format!("{} event publisher", publication.event.name()),
+                Criticality::Critical,
+                move || {
+                    publication_task(
+                        state.clone(),
+                        session.clone(),
+                        publication.clone(),
+                        uplink.clone(),
+                    )
+                },
+            );
+        }
 
-                            // Wait before publishing the next state
-                            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to create Zenoh publisher for the Lock State Event: {:?}",
-                            e
-                        );
-                    }
-                }
-            })
-        };
+        // Task to replay the events buffered while the cloud was unreachable
+        if let Some(buffer) = &self.buffer {
+            let buffer = Arc::clone(buffer);
+            let session = session.clone();
+            let retry_interval = self.buffer_retry_interval;
+            supervisor.spawn("buffered event replay", Criticality::Optional, move || {
+                replay_buffered_events(Arc::clone(&buffer), session.clone(), retry_interval)
+            });
+        }
 
-        let battery_publisher = {
-            let state = Arc::clone(&self.state);
-            let session = Arc::clone(&session); // Cloning session for use inside async block
-            tokio::spawn(async move {
-                match ZenohPublisher::new(session.clone(), CLOUD_BATTERY_TOPIC).await {
-                    Ok(publisher) => {
-                        loop {
-                            let event = {
-                                let vehicle_state = state.lock().await;
-                                vehicle_state.to_battery_event()
-                            };
-                            // Publish vehicle state to the cloud
-                            if let Some(event) = event {
-                                trace!("Publishing battery data event to the cloud: {:?}", event);
-                                if let Err(e) = publisher.publish(event).await {
-                                    error!("Failed to publish battery state: {:?}", e);
-                                }
-                            } else {
-                                error!("Failed to create battery event");
-                            }
+        // Tasks to receive the configured commands from the cloud
+        for subscription in &self.subscriptions {
+            let session = session.clone();
+            let subscription = subscription.clone();
+            let command_tx = command_tx.clone();
+            let reporter = reporter.clone();
+            supervisor.spawn(
+                format!("{} command receiver", subscription.command.name()),
+                Criticality::Critical,
+                move || {
+                    receive_commands(
+                        session.clone(),
+                        subscription.clone(),
+                        command_tx.clone(),
+                        reporter.clone(),
+                    )
+                },
+            );
+        }
 
-                            // Wait before publishing the next state
-                            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to create Zenoh publisher for the Battery Event: {:?}",
-                            e
-                        );
-                    }
-                }
-            })
-        };
+        // Task to publish the status of received commands to the cloud
+        supervisor.spawn_once(
+            "command status publisher",
+            publish_command_status(session, self.status_key_expr.clone(), status_rx),
+        );
+    }
+}
 
-        // Task to periodically publish trip data to the cloud
-        let trip_data_publisher = {
-            let state = Arc::clone(&self.state);
-            let session = Arc::clone(&session); // Cloning session for use inside async block
-            tokio::spawn(async move {
-                match ZenohPublisher::new(session.clone(), CLOUD_TRIP_DATA_TOPIC).await {
-                    Ok(publisher) => {
-                        loop {
-                            let event = {
-                                let vehicle_state = state.lock().await;
-                                vehicle_state.to_trip_data_event()
-                            };
-                            // Publish vehicle state to the cloud
-                            if let Some(event) = event {
-                                trace!("Publishing trip data event to the cloud: {:?}", event);
-                                if let Err(e) = publisher.publish(event).await {
-                                    error!("Failed to publish speed: {:?}", e);
-                                }
-                            } else {
-                                error!("Failed to create speed event");
-                            }
+fn publication_task(
+    state: Arc<Mutex<VehicleState>>,
+    session: Arc<zenoh::Session>,
+    publication: EventPublication,
+    uplink: Uplink,
+) -> BoxFuture<'static, ()> {
+    match publication.event {
+        CloudEvent::Battery => publish_events(
+            state,
+            session,
+            publication,
+            uplink,
+            VehicleState::to_battery_event,
+        )
+        .boxed(),
+        CloudEvent::Speed => publish_events(
+            state,
+            session,
+            publication,
+            uplink,
+            VehicleState::to_speed_event,
+        )
+        .boxed(),
+        CloudEvent::CurrentLocation => publish_events(
+            state,
+            session,
+            publication,
+            uplink,
+            VehicleState::to_current_location_event,
+        )
+        .boxed(),
+        CloudEvent::Exterior => publish_events(
+            state,
+            session,
+            publication,
+            uplink,
+            VehicleState::to_exterior_event,
+        )
+        .boxed(),
+        CloudEvent::Tires => publish_events(
+            state,
+            session,
+            publication,
+            uplink,
+            VehicleState::to_tires_event,
+        )
+        .boxed(),
+        CloudEvent::SystemState => publish_events(
+            state,
+            session,
+            publication,
+            uplink,
+            VehicleState::to_state_event,
+        )
+        .boxed(),
+        CloudEvent::TripData => publish_events(
+            state,
+            session,
+            publication,
+            uplink,
+            VehicleState::to_trip_data_event,
+        )
+        .boxed(),
+    }
+}
 
-                            // Wait before publishing the next state
-                            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+// Periodically builds an event from the vehicle state and publishes it to the
+// cloud whenever its publish policy allows it
+async fn publish_events<T, F>(
+    state: Arc<Mutex<VehicleState>>,
+    session: Arc<zenoh::Session>,
+    publication: EventPublication,
+    uplink: Uplink,
+    to_event: F,
+) where
+    T: TopicMessage + Debug,
+    F: Fn(&VehicleState) -> Option<T> + Send + 'static,
+{
+    let name = publication.event.name();
+    let mut gate = PublishGate::new(&publication.policy);
+    let topic = Topic::from_key_expr(publication.topic.clone(), publication.format);
+    match TopicPublisher::new(session, topic).await {
+        Ok(publisher) => {
+            info!(
+                "Publishing {} events to '{}' every {:?} ({:?}, {:?})",
+                name,
+                publication.topic,
+                publication.frequency,
+                publication.policy.mode,
+                publication.format
+            );
+            loop {
+                let (event, stale_signals) = {
+                    let mut vehicle_state = state.lock().await;
+                    let stale_signals = vehicle_state
+                        .freshness
+                        .stale_signals(publication.event.signals());
+                    (to_event(&vehicle_state), stale_signals)
+                };
+                // Publish vehicle state to the cloud
+                if !stale_signals.is_empty() && publication.on_stale == StaleAction::Suppress {
+                    trace!("Suppressing {} event with stale signals", name);
+                } else if let Some(event) = event {
+                    let now = Instant::now();
+                    match gate.check(&event, now) {
+                        Some(value) => {
+                            trace!("Publishing {} event to the cloud: {:?}", name, event);
+                            let stale_signals =
+                                stale_signals.iter().map(|s| s.to_string()).collect();
+                            let result = uplink
+                                .send(&publisher, publication.priority, event, stale_signals)
+                                .await;
+                            match result {
+                                Ok(_) => gate.published(value, now),
+                                Err(e) => error!("Failed to publish {} event: {:?}", name, e),
+                            }
                         }
+                        None => trace!("Skipping unchanged {} event", name),
                     }
-                    Err(e) => {
-                        error!(
-                            "Failed to create Zenoh publisher for the Trip Data Event: {:?}",
-                            e
-                        );
-                    }
+                } else {
+                    error!("Failed to create {} event", name);
                 }
-            })
-        };
 
-        // Task to periodically publish speed data to the cloud
-        let speed_publisher = {
-            let state = Arc::clone(&self.state);
-            let session = Arc::clone(&session); // Cloning session for use inside async block
-            tokio::spawn(async move {
-                match ZenohPublisher::new(session.clone(), CLOUD_SPEED_TOPIC).await {
-                    Ok(publisher) => {
-                        loop {
-                            let event = {
-                                let vehicle_state = state.lock().await;
-                                vehicle_state.to_speed_event()
-                            };
-                            // Publish vehicle state to the cloud
-                            if let Some(event) = event {
-                                trace!("Publishing speed event to the cloud: {:?}", event);
-                                if let Err(e) = publisher.publish(event).await {
-                                    error!("Failed to publish speed: {:?}", e);
-                                }
-                            } else {
-                                error!("Failed to create speed event");
-                            }
+                // Wait before publishing the next state
+                tokio::time::sleep(publication.frequency).await;
+            }
+        }
+        Err(e) => {
+            error!(
+                "Failed to create Zenoh publisher for the {} Event: {:?}",
+                name, e
+            );
+        }
+    }
+}
 
-                            // Wait before publishing the next state
-                            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to create Zenoh publisher for the Speed Event: {:?}",
-                            e
-                        );
-                    }
-                }
-            })
-        };
+// Decodes commands received from the cloud and forwards them to the command processor
+async fn receive_commands(
+    session: Arc<zenoh::Session>,
+    subscription: CommandSubscription,
+    command_tx: mpsc::Sender<CommandRequest>,
+    reporter: CommandReporter,
+) {
+    let command = subscription.command;
+    let name = command.name();
+    let subscriber = match ZenohSubscriber::new(session, subscription.key_expr.clone()).await {
+        Ok(subscriber) => subscriber,
+        Err(e) => {
+            error!("Failed to create subscriber for {} commands: {:?}", name, e);
+            return;
+        }
+    };
+    info!("Receiving {} commands on '{}'", name, subscription.key_expr);
 
-        // Task to periodically publish exterior data to the cloud
-        let exterior_publisher = {
-            let state = Arc::clone(&self.state);
-            let session = Arc::clone(&session); // Cloning session for use inside async block
-            tokio::spawn(async move {
-                match ZenohPublisher::new(session.clone(), CLOUD_EXTERIOR_TOPIC).await {
-                    Ok(publisher) => {
-                        loop {
-                            let event = {
-                                let vehicle_state = state.lock().await;
-                                vehicle_state.to_exterior_event()
-                            };
-                            // Publish vehicle state to the cloud
-                            if let Some(event) = event {
-                                trace!("Publishing exterior event to the cloud: {:?}", event);
-                                if let Err(e) = publisher.publish(event).await {
-                                    error!("Failed to publish exterior event: {:?}", e);
-                                }
-                            } else {
-                                error!("Failed to create exterior event");
-                            }
-                            // Wait before publishing the next state
-                            tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to create Zenoh publisher for the Exterior Event: {:?}",
-                            e
-                        );
-                    }
-                }
-            })
-        };
+    while let Ok(sample) = subscriber.subscriber.recv_async().await {
+        // The cloud passes the correlation id as attachment, generate one otherwise
+        let correlation_id = sample
+            .attachment()
+            .and_then(|attachment| attachment.try_to_string().ok())
+            .map(|id| id.into_owned())
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| generate_correlation_id(reporter.vehicle_id()));
+        info!(
+            "Received {} command {} from the cloud",
+            name, correlation_id
+        );
 
-        // Task to periodically publish tires data to the cloud
-        let tires_publisher = {
-            let state = Arc::clone(&self.state);
-            let session = Arc::clone(&session); // Cloning session for use inside async block
-            tokio::spawn(async move {
-                match ZenohPublisher::new(session.clone(), CLOUD_TIRES_TOPIC).await {
-                    Ok(publisher) => {
-                        loop {
-                            let event = {
-                                let vehicle_state = state.lock().await;
-                                vehicle_state.to_tires_event()
-                            };
-                            // Publish vehicle state to the cloud
-                            if let Some(event) = event {
-                                trace!("Publishing tires event to the cloud: {:?}", event);
-                                if let Err(e) = publisher.publish(event).await {
-                                    error!("Failed to publish tires: {:?}", e);
-                                }
-                            } else {
-                                error!("Failed to create tires event");
-                            }
-                            // Wait before publishing the next state
-                            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to create Zenoh publisher for the Tires Event: {:?}",
-                            e
-                        );
-                    }
+        let bytes = sample.payload().to_bytes();
+        match command.decode(&bytes) {
+            Ok(vehicle_command) => {
+                let request = CommandRequest::new(correlation_id, vehicle_command);
+                if let Err(e) = command_tx.send(request).await {
+                    error!("Failed to forward command: {:?}", e);
+                    break;
                 }
-            })
-        };
+            }
+            Err(e) => {
+                error!("Failed to decode {} message: {:?}", name, e);
+                reporter
+                    .report_raw(
+                        &correlation_id,
+                        name,
+                        CommandStatus::Rejected,
+                        &format!("Failed to decode command: {}", e),
+                    )
+                    .await;
+            }
+        }
+    }
+}
 
-        let location_publisher = {
-            let state = Arc::clone(&self.state);
-            let session = Arc::clone(&session); // Cloning session for use inside async block
-            tokio::spawn(async move {
-                match ZenohPublisher::new(session.clone(), CLOUD_LOCATION_TOPIC).await {
-                    Ok(publisher) => {
-                        loop {
-                            let event = {
-                                let vehicle_state = state.lock().await;
-                                vehicle_state.to_current_location_event()
-                            };
-                            // Publish vehicle state to the cloud
-                            if let Some(event) = event {
-                                trace!(
-                                    "Publishing current location event to the cloud: {:?}",
-                                    event
-                                );
-                                if let Err(e) = publisher.publish(event).await {
-                                    error!("Failed to publish location: {:?}", e);
-                                }
-                            } else {
-                                error!("Failed to create location event");
-                            }
-                            // Wait before publishing the next state
-                            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to create Zenoh publisher for the Location Event: {:?}",
-                            e
-                        );
-                    }
-                }
-            })
-        };
-
-        // Task to receive commands from the cloud
-        let command_receiver = {
-            // Cloning session for use inside async block
-            let session = Arc::clone(&session);
-            let lock_command_subscriber =
-                ZenohSubscriber::new(session.clone(), "cloud/command/VEHICLE1VIN/lock").await?;
-            let turn_on_off_command_subscriber =
-                ZenohSubscriber::new(session.clone(), "cloud/command/VEHICLE1VIN/turn_on_off")
-                    .await?;
-
-            tokio::spawn(async move {
-                loop {
-                    tokio::select! {
-                        // Handle lock/unlock commands
-                        lock_sample = lock_command_subscriber.subscriber.recv_async() => {
-                            match lock_sample {
-                                Ok(sample) => {
-                                    info!("Received lock/unclock command from the cloud");
-                                    let bytes = sample.payload().to_bytes();
-                                    match LockUnlockCommand::decode(&*bytes) {
-                                        Ok(message) => {
-                                            let vehicle_command = if message.state == LockState::Lock as i32 {
-                                                VehicleCommand::Lock
-                                            } else {
-                                                VehicleCommand::Unlock
-                                            };
-
-                                            if let Err(e) = command_tx.send(vehicle_command).await {
-                                                error!("Failed to forward command: {:?}", e);
-                                                break;
-                                            }
-                                        }
-                                        Err(e) => {
-                                            error!("Failed to decode lock/unlock message: {:?}", e);
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("Failed to receive lock/unlock command: {:?}", e);
-                                }
-                            }
-                        }
+// Publishes command status events to the cloud
+async fn publish_command_status(
+    session: Arc<zenoh::Session>,
+    key_expr: String,
+    mut status_rx: mpsc::Receiver<CommandStatusEvent>,
+) {
+    let topic = Topic::from_key_expr(key_expr.clone(), WireFormat::Protobuf);
+    let publisher = match TopicPublisher::<CommandStatusEvent>::new(session, topic).await {
+        Ok(publisher) => publisher,
+        Err(e) => {
+            error!("Failed to create command status publisher: {:?}", e);
+            return;
+        }
+    };
+    info!("Publishing command status on '{}'", key_expr);
 
-                        // Handle turn on/off commands
-                        turn_on_off_sample = turn_on_off_command_subscriber.subscriber.recv_async() => {
-                            match turn_on_off_sample {
-                                Ok(sample) => {
-                                    info!("Received turn_on_off command from the cloud");
-
-                                    let bytes = sample.payload().to_bytes();
-                                    match GeneralStateCommand::decode(&*bytes) {
-                                        Ok(message) => {
-                                            let vehicle_command = if message.state == CommandState::On as i32
-                                                && message.target == CommandTarget::Lights as i32
-                                            {
-                                                VehicleCommand::LightOn
-                                            } else if message.state == CommandState::Off as i32
-                                                && message.target == CommandTarget::Lights as i32
-                                            {
-                                                VehicleCommand::LightOff
-                                            } else if message.state == CommandState::On as i32
-                                                && message.target == CommandTarget::Engine as i32
-                                            {
-                                                VehicleCommand::EngineOn
-                                            } else if message.state == CommandState::Off as i32
-                                                && message.target == CommandTarget::Engine as i32
-                                            {
-                                                VehicleCommand::EngineOff
-                                            } else if message.state == CommandState::On as i32
-                                                && message.target == CommandTarget::Horn as i32
-                                            {
-                                                VehicleCommand::HornOn
-                                            } else {
-                                                VehicleCommand::HornOff
-                                            };
-
-                                            if let Err(e) = command_tx.send(vehicle_command).await {
-                                                error!("Failed to forward command: {:?}", e);
-                                                break;
-                                            }
-                                        }
-                                        Err(e) => {
-                                            error!("Failed to decode turn_on_off message: {:?}", e);
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("Failed to receive turn_on_off command: {:?}", e);
-                                }
-                            }
-                        }
-                    }
-                }
-            })
-        };
-
-        Ok(vec![
-            lock_state_publisher,
-            battery_publisher,
-            trip_data_publisher,
-            speed_publisher,
-            exterior_publisher,
-            tires_publisher,
-            location_publisher,
-            command_receiver,
-        ])
+    while let Some(event) = status_rx.recv().await {
+        trace!("Publishing command status event to the cloud: {:?}", event);
+        if let Err(e) = publisher.publish(event).await {
+            error!("Failed to publish command status: {:?}", e);
+        }
     }
 }
@@ -1,97 +1,135 @@
-use crate::vehicle_state::{VehicleCommand, VehicleState};
-use common::topics::LOCK_STATE_TOPIC;
+use crate::command_status::{CommandReporter, CommandRequest};
+use crate::supervisor::Supervisor;
+use crate::vehicle_state::{LowVoltageSystemState, VehicleCommand, VehicleState};
+use common::topics::{HORN_COMMAND, LIGHTS_COMMAND, LOCK_STATE_COMMAND, POWERTRAIN_COMMAND};
 use common::DataPublisher;
-use common::ZenohPublisher;
+use common::{TopicMessage, TopicPublisher};
 use log::{error, info, warn};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
-use tokio::task::JoinHandle;
+use vehicle_msgs::horn::Horn;
+use vehicle_msgs::lights::Lights;
+use vehicle_msgs::powertrain::PowertrainState;
+use vehicle_msgs::state::{LockState, State};
+use vehicle_msgs::vehicle_command_status::CommandStatus;
+
+// How often the vehicle state is checked while waiting for a command to be applied
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Publishers forwarding commands to the in-vehicle actuators, on topics apart
+// from the state the vehicle reports back
+struct VehiclePublishers {
+    lock_state: TopicPublisher<LockState>,
+    lights: TopicPublisher<Lights>,
+    horn: TopicPublisher<Horn>,
+    powertrain: TopicPublisher<PowertrainState>,
+}
+
+impl VehiclePublishers {
+    async fn new(
+        session: Arc<zenoh::Session>,
+    ) -> Result<VehiclePublishers, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(VehiclePublishers {
+            lock_state: TopicPublisher::new(session.clone(), LOCK_STATE_COMMAND).await?,
+            lights: TopicPublisher::new(session.clone(), LIGHTS_COMMAND).await?,
+            horn: TopicPublisher::new(session.clone(), HORN_COMMAND).await?,
+            powertrain: TopicPublisher::new(session, POWERTRAIN_COMMAND).await?,
+        })
+    }
+}
 
 pub struct CommandProcessor {
     state: Arc<Mutex<VehicleState>>, // VehicleState to verify commands
+    command_timeout: Duration,       // Time the vehicle has to apply a command
 }
 
 impl CommandProcessor {
-    pub fn new(state: Arc<Mutex<VehicleState>>) -> Self {
-        Self { state }
+    pub fn new(state: Arc<Mutex<VehicleState>>, command_timeout: Duration) -> Self {
+        Self {
+            state,
+            command_timeout,
+        }
     }
 
     pub fn run(
         &self,
         session: Arc<zenoh::Session>,
-        mut command_rx: mpsc::Receiver<VehicleCommand>,
-    ) -> JoinHandle<()> {
+        mut command_rx: mpsc::Receiver<CommandRequest>,
+        reporter: CommandReporter,
+        supervisor: &mut Supervisor,
+    ) {
         let vehicle_state = self.state.clone();
-        tokio::spawn(async move {
-            match ZenohPublisher::new(session, LOCK_STATE_TOPIC).await {
-                Ok(state_publisher) => {
-                    while let Some(command) = command_rx.recv().await {
-                        info!("Received command from cloud: {:?}", command);
+        let command_timeout = self.command_timeout;
+        supervisor.spawn_once("command processor", async move {
+            match VehiclePublishers::new(session).await {
+                Ok(publishers) => {
+                    while let Some(request) = command_rx.recv().await {
+                        let command = request.command;
+                        info!("Received command from cloud: {:?}", request);
 
                         // Validate the command
-                        {
-                            if !vehicle_state.lock().await.is_valid_command(&command) {
-                                warn!("Invalid command: {:?}", command);
-                                continue;
-                            }
+                        let validation = vehicle_state.lock().await.validate_command(&command);
+                        if let Err(reason) = validation {
+                            warn!("Rejected command {:?}: {}", command, reason);
+                            reporter
+                                .report(&request, CommandStatus::Rejected, &reason)
+                                .await;
+                            continue;
                         }
+                        reporter.report(&request, CommandStatus::Accepted, "").await;
 
                         // Forward the command to the in-vehicle system
-                        match command {
+                        info!("Forwarding {:?} command to in-vehicle system", command);
+                        let result = match command {
                             VehicleCommand::Lock => {
-                                info!("Forwarding Lock command to in-vehicle system");
-                                let new_state = vehicle_msgs::state::LockState {
-                                    state: vehicle_msgs::state::State::Lock as i32,
+                                let new_state = LockState {
+                                    state: State::from(LowVoltageSystemState::LOCK) as i32,
                                 };
-                                match state_publisher.publish(new_state).await {
-                                    Ok(_) => {
-                                        info!("Published lock state");
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to publish lock state {:?}", e);
-                                    }
-                                }
+                                forward(&publishers.lock_state, new_state, "lock state").await
                             }
                             VehicleCommand::Unlock => {
-                                info!("Forwarding Unlock command to in-vehicle system");
-                                let new_state = vehicle_msgs::state::LockState {
-                                    state: vehicle_msgs::state::State::On as i32,
+                                let new_state = LockState {
+                                    state: State::from(LowVoltageSystemState::ON) as i32,
                                 };
-
-                                // Publish the new unlock state
-                                match state_publisher.publish(new_state).await {
-                                    Ok(_) => {
-                                        info!("Published unlock state");
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to publish unlock state: {:?}", e);
-                                    }
-                                }
-                            }
-                            VehicleCommand::LightOn => {
-                                info!("Forwarding LightOn command to in-vehicle system");
-                                // command_publisher.publish(command).await?;
+                                forward(&publishers.lock_state, new_state, "unlock state").await
                             }
-                            VehicleCommand::LightOff => {
-                                info!("Forwarding LightOff command to in-vehicle system");
-                                // command_publisher.publish(command).await?;
+                            VehicleCommand::LightOn | VehicleCommand::LightOff => {
+                                // Only the low beam is switched, other lights keep their state
+                                let mut lights = vehicle_state.lock().await.to_lights();
+                                lights.is_low_beam_on = matches!(command, VehicleCommand::LightOn);
+                                forward(&publishers.lights, lights, "lights state").await
                             }
-                            VehicleCommand::HornOn => {
-                                info!("Forwarding HornOn command to in-vehicle system");
-                                // command_publisher.publish(command).await?;
+                            VehicleCommand::HornOn | VehicleCommand::HornOff => {
+                                let horn = Horn {
+                                    is_active: matches!(command, VehicleCommand::HornOn),
+                                };
+                                forward(&publishers.horn, horn, "horn state").await
                             }
-                            VehicleCommand::HornOff => {
-                                info!("Forwarding HornOff command to in-vehicle system");
-                                // command_publisher.publish(command).await?;
+                            VehicleCommand::EngineOn | VehicleCommand::EngineOff => {
+                                let powertrain = PowertrainState {
+                                    is_engine_on: matches!(command, VehicleCommand::EngineOn),
+                                };
+                                forward(&publishers.powertrain, powertrain, "powertrain state")
+                                    .await
                             }
-                            VehicleCommand::EngineOn => {
-                                info!("Forwarding Unlock command to in-vehicle system");
-                                // command_publisher.EngineOn(command).await?;
+                        };
+
+                        match result {
+                            Ok(_) => {
+                                vehicle_state.lock().await.command_forwarded(&command);
+                                spawn_confirmation(
+                                    vehicle_state.clone(),
+                                    reporter.clone(),
+                                    request,
+                                    command_timeout,
+                                )
                             }
-                            VehicleCommand::EngineOff => {
-                                info!("Forwarding EngineOff command to in-vehicle system");
-                                // command_publisher.publish(command).await?;
+                            Err(e) => {
+                                reporter
+                                    .report(&request, CommandStatus::Failed, &e.to_string())
+                                    .await
                             }
                         }
                     }
@@ -103,6 +141,56 @@ impl CommandProcessor {
                     );
                 }
             }
-        })
+        });
+    }
+}
+
+async fn forward<T>(
+    publisher: &TopicPublisher<T>,
+    message: T,
+    description: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    T: TopicMessage,
+{
+    match publisher.publish(message).await {
+        Ok(_) => {
+            info!("Published {}", description);
+            Ok(())
+        }
+        Err(e) => {
+            error!("Failed to publish {}: {:?}", description, e);
+            Err(e)
+        }
     }
 }
+
+// Waits for the vehicle to report the state requested by a command
+fn spawn_confirmation(
+    state: Arc<Mutex<VehicleState>>,
+    reporter: CommandReporter,
+    request: CommandRequest,
+    command_timeout: Duration,
+) {
+    tokio::spawn(async move {
+        let applied = tokio::time::timeout(command_timeout, async {
+            while !state.lock().await.is_command_applied(&request.command) {
+                tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+            }
+        })
+        .await;
+
+        match applied {
+            Ok(_) => reporter.report(&request, CommandStatus::Executed, "").await,
+            Err(_) => {
+                let reason = format!(
+                    "Vehicle did not apply the command within {:?}",
+                    command_timeout
+                );
+                reporter
+                    .report(&request, CommandStatus::TimedOut, &reason)
+                    .await
+            }
+        }
+    });
+}
@@ -0,0 +1,118 @@
+use crate::config::{CommandRule, RuleCondition, RuleOperator};
+use crate::vehicle_state::VehicleCommand;
+use log::warn;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use vehicle_msgs::vehicle_msgs::Vehicle;
+
+// A configured rule bound to the command it applies to
+#[derive(Debug, Clone)]
+struct BoundRule {
+    command: VehicleCommand,
+    reject_if: Option<RuleCondition>,
+    cooldown: Option<Duration>,
+    reason: Option<String>,
+}
+
+// Preconditions commands have to satisfy before being forwarded to the vehicle
+#[derive(Debug, Default)]
+pub struct CommandRules {
+    rules: Vec<BoundRule>,
+    last_forwarded: HashMap<VehicleCommand, Instant>,
+}
+
+impl CommandRules {
+    pub fn new(rules: &[CommandRule]) -> Result<Self, String> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                if rule.reject_if.is_none() && rule.cooldown.is_none() {
+                    return Err(format!(
+                        "Command rule for '{}' needs a 'reject_if' condition or a 'cooldown'",
+                        rule.command
+                    ));
+                }
+                Ok(BoundRule {
+                    command: rule.command.parse()?,
+                    reject_if: rule.reject_if.clone(),
+                    cooldown: rule.cooldown.map(Duration::from_millis),
+                    reason: rule.reason.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self {
+            rules,
+            last_forwarded: HashMap::new(),
+        })
+    }
+
+    // Returns the rejection reason of the first rule the command violates
+    pub fn check(&self, command: &VehicleCommand, vehicle: &Vehicle) -> Result<(), String> {
+        let mut vehicle_value = None;
+        for rule in self.rules.iter().filter(|rule| rule.command == *command) {
+            if let Some(cooldown) = rule.cooldown {
+                if let Some(last) = self.last_forwarded.get(command) {
+                    if last.elapsed() < cooldown {
+                        return Err(rule.reason.clone().unwrap_or_else(|| {
+                            format!("{:?} is limited to once every {:?}", command, cooldown)
+                        }));
+                    }
+                }
+            }
+
+            if let Some(condition) = &rule.reject_if {
+                // The vehicle model is only serialized when a condition has to be evaluated
+                let vehicle_value = vehicle_value
+                    .get_or_insert_with(|| serde_json::to_value(vehicle).unwrap_or_default());
+                if condition_holds(condition, vehicle_value) {
+                    return Err(rule.reason.clone().unwrap_or_else(|| {
+                        format!(
+                            "{:?} is not allowed while {} is {:?} {}",
+                            command, condition.field, condition.operator, condition.value
+                        )
+                    }));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Records a command forwarded to the vehicle for cooldown rules
+    pub fn forwarded(&mut self, command: &VehicleCommand) {
+        self.last_forwarded.insert(*command, Instant::now());
+    }
+}
+
+fn condition_holds(condition: &RuleCondition, vehicle: &Value) -> bool {
+    let pointer = format!("/{}", condition.field.replace('.', "/"));
+    let Some(actual) = vehicle.pointer(&pointer) else {
+        warn!(
+            "Command rule field '{}' is not set in the vehicle state",
+            condition.field
+        );
+        return false;
+    };
+
+    match (actual.as_f64(), condition.value.as_f64()) {
+        (Some(actual), Some(expected)) => match condition.operator {
+            RuleOperator::Eq => actual == expected,
+            RuleOperator::Ne => actual != expected,
+            RuleOperator::Gt => actual > expected,
+            RuleOperator::Ge => actual >= expected,
+            RuleOperator::Lt => actual < expected,
+            RuleOperator::Le => actual <= expected,
+        },
+        _ => match condition.operator {
+            RuleOperator::Eq => *actual == condition.value,
+            RuleOperator::Ne => *actual != condition.value,
+            _ => {
+                warn!(
+                    "Command rule on '{}' compares non-numeric values with {:?}",
+                    condition.field, condition.operator
+                );
+                false
+            }
+        },
+    }
+}
@@ -0,0 +1,95 @@
+use crate::vehicle_state::VehicleCommand;
+use log::{error, info};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
+use vehicle_msgs::vehicle_command_status::{CommandStatus, CommandStatusEvent};
+
+static NEXT_COMMAND_ID: AtomicU64 = AtomicU64::new(0);
+
+// A vehicle command together with the id used to correlate its status events
+#[derive(Debug, Clone)]
+pub struct CommandRequest {
+    pub correlation_id: String,
+    pub command: VehicleCommand,
+}
+
+impl CommandRequest {
+    pub fn new(correlation_id: String, command: VehicleCommand) -> Self {
+        Self {
+            correlation_id,
+            command,
+        }
+    }
+}
+
+// Generates a correlation id for commands that were sent without one
+pub fn generate_correlation_id(vehicle_id: &str) -> String {
+    format!(
+        "{}-{}-{}",
+        vehicle_id,
+        chrono::Utc::now().timestamp_millis(),
+        NEXT_COMMAND_ID.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+// Reports the status of cloud commands back to the cloud
+#[derive(Debug, Clone)]
+pub struct CommandReporter {
+    vehicle_id: String,
+    status_tx: mpsc::Sender<CommandStatusEvent>,
+}
+
+impl CommandReporter {
+    pub fn new(vehicle_id: String, status_tx: mpsc::Sender<CommandStatusEvent>) -> Self {
+        Self {
+            vehicle_id,
+            status_tx,
+        }
+    }
+
+    pub fn vehicle_id(&self) -> &str {
+        &self.vehicle_id
+    }
+
+    pub async fn report(&self, request: &CommandRequest, status: CommandStatus, reason: &str) {
+        self.report_raw(
+            &request.correlation_id,
+            &format!("{:?}", request.command),
+            status,
+            reason,
+        )
+        .await;
+    }
+
+    // Reports a status for a command that could not be turned into a `VehicleCommand`
+    pub async fn report_raw(
+        &self,
+        correlation_id: &str,
+        command: &str,
+        status: CommandStatus,
+        reason: &str,
+    ) {
+        info!(
+            "Command {} ({}): {}{}",
+            correlation_id,
+            command,
+            status.as_str_name(),
+            if reason.is_empty() {
+                String::new()
+            } else {
+                format!(" - {}", reason)
+            }
+        );
+        let event = CommandStatusEvent {
+            vehicle_id: self.vehicle_id.clone(),
+            correlation_id: correlation_id.to_string(),
+            command: command.to_string(),
+            status: status as i32,
+            reason: reason.to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        };
+        if let Err(e) = self.status_tx.send(event).await {
+            error!("Failed to forward command status: {:?}", e);
+        }
+    }
+}
@@ -1,11 +1,277 @@
+use common::{WireFormat, ZenohSessionConfig};
 use serde::Deserialize;
+use std::collections::HashMap;
 
 #[derive(Debug, Deserialize)]
 pub struct TwinServiceConfig {
+    // Shorthand for `zenoh.connect`
+    #[serde(default)]
     pub zenoh_endpoints: Vec<String>,
+    #[serde(default)]
+    pub zenoh: ZenohSessionConfig,
     pub vehicle_id: String,
     pub events: Vec<Event>,
     pub commands: Vec<Command>,
+    // Preconditions checked before a command is forwarded to the vehicle
+    #[serde(default)]
+    pub command_rules: Vec<CommandRule>,
+    // Key expression command status events are published on
+    #[serde(default = "default_command_status_topic")]
+    pub command_status_topic: String,
+    // Time the vehicle has to apply a command before it is reported as timed out
+    #[serde(default = "default_command_timeout")]
+    pub command_timeout: u64, // in milliseconds
+    // Key expression prefix the digital twin can be queried on
+    #[serde(default = "default_twin_key_expr")]
+    pub twin_key_expr: String,
+    // Periodic and on-shutdown snapshots of the twin state, restored on startup
+    #[serde(default)]
+    pub snapshot: Option<SnapshotConfig>,
+    // Restart and shutdown behavior of the service tasks
+    #[serde(default)]
+    pub supervisor: SupervisorConfig,
+    // Intra-vehicle topic to the time in milliseconds after which its last
+    // value is considered stale
+    #[serde(default)]
+    pub signal_timeouts: HashMap<String, u64>,
+    // On-disk buffer for cloud events that can't be published
+    #[serde(default)]
+    pub buffer: Option<BufferConfig>,
+    // Aggregates cloud events into compressed batches on a single key expression
+    #[serde(default)]
+    pub batching: Option<BatchingConfig>,
+    // Mirrors the whole vehicle model to the cloud as snapshots and deltas
+    #[serde(default)]
+    pub sync: Option<TwinSyncConfig>,
+    // Desired state set by the cloud and reconciled through commands
+    #[serde(default)]
+    pub shadow: Option<ShadowConfig>,
+}
+
+fn default_command_status_topic() -> String {
+    "cloud/command/{vehicle_id}/status".to_string()
+}
+
+fn default_command_timeout() -> u64 {
+    5000
+}
+
+fn default_twin_key_expr() -> String {
+    "vehicle/{vehicle_id}/twin".to_string()
+}
+
+impl TwinServiceConfig {
+    // Zenoh session options, with `zenoh_endpoints` used as connect endpoints
+    // unless `zenoh.connect` is set
+    pub fn session_config(&self) -> ZenohSessionConfig {
+        let mut session_config = self.zenoh.clone();
+        if session_config.connect.is_empty() {
+            session_config.connect = self.zenoh_endpoints.clone();
+        }
+        session_config
+    }
+
+    // Expands the `{vehicle_id}` placeholder of a configured key expression
+    pub fn resolve_topic(&self, topic: &str) -> Result<String, String> {
+        let resolved = topic.replace("{vehicle_id}", &self.vehicle_id);
+        if resolved.contains(['{', '}']) {
+            return Err(format!("Unknown placeholder in topic '{}'", topic));
+        }
+        Ok(resolved)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SupervisorConfig {
+    // Restarts of a stopped task before it is given up on
+    pub max_restarts: u32,
+    pub initial_backoff: u64, // in milliseconds, doubled on every restart
+    pub max_backoff: u64,     // in milliseconds
+    // Time tasks have to stop on shutdown before they are aborted
+    pub shutdown_timeout: u64, // in milliseconds
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            initial_backoff: 500,
+            max_backoff: 30000,
+            shutdown_timeout: 5000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BufferConfig {
+    // Directory the buffered events are stored in
+    pub path: String,
+    #[serde(default = "default_buffer_max_size")]
+    pub max_size: u64, // in bytes
+    #[serde(default)]
+    pub drop_policy: DropPolicy,
+    // How often buffered events are retried
+    #[serde(default = "default_buffer_retry_interval")]
+    pub retry_interval: u64, // in milliseconds
+}
+
+fn default_buffer_max_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_buffer_retry_interval() -> u64 {
+    1000
+}
+
+// Which event to drop when the buffer is full
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DropPolicy {
+    // Make room by dropping the oldest event of the lowest priority
+    #[default]
+    Oldest,
+    // Keep the buffered events and drop the new one
+    Newest,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BatchingConfig {
+    // Key expression the batches are published on
+    #[serde(default = "default_batch_topic")]
+    pub topic: String,
+    // Time events are collected for before the batch is published
+    #[serde(default = "default_batch_window")]
+    pub window: u64, // in milliseconds
+    // Events that cause the batch to be published before the window is over
+    #[serde(default = "default_batch_max_events")]
+    pub max_events: usize,
+    #[serde(default)]
+    pub compression: BatchCompression,
+}
+
+fn default_batch_topic() -> String {
+    "cloud/telemetry/{vehicle_id}/batch".to_string()
+}
+
+fn default_batch_window() -> u64 {
+    5000
+}
+
+fn default_batch_max_events() -> usize {
+    100
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchCompression {
+    #[default]
+    None,
+    Zstd,
+    Lz4,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TwinSyncConfig {
+    // Prefix of the `/snapshot` and `/delta` key expressions
+    #[serde(default = "default_sync_key_expr")]
+    pub key_expr: String,
+    // How often the model is checked for changes
+    #[serde(default = "default_sync_interval")]
+    pub interval: u64, // in milliseconds
+    #[serde(default)]
+    pub format: WireFormat,
+}
+
+fn default_sync_key_expr() -> String {
+    "cloud/twin/{vehicle_id}".to_string()
+}
+
+fn default_sync_interval() -> u64 {
+    1000
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShadowConfig {
+    // Prefix of the `/desired` and `/state` key expressions
+    #[serde(default = "default_shadow_key_expr")]
+    pub key_expr: String,
+    // How often the state is compared and unapplied commands are reissued
+    #[serde(default = "default_shadow_retry_interval")]
+    pub retry_interval: u64, // in milliseconds
+    // Time the vehicle has to reach the desired state
+    #[serde(default = "default_shadow_timeout")]
+    pub timeout: u64, // in milliseconds
+}
+
+fn default_shadow_key_expr() -> String {
+    "cloud/shadow/{vehicle_id}".to_string()
+}
+
+fn default_shadow_retry_interval() -> u64 {
+    2000
+}
+
+fn default_shadow_timeout() -> u64 {
+    30000
+}
+
+// Mapping file declaring how intra-vehicle messages are written to the twin,
+// in addition to the built-in signals
+#[derive(Debug, Deserialize, Clone)]
+pub struct SignalMappingConfig {
+    pub signals: Vec<SignalMapping>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SignalMapping {
+    // Intra-vehicle key expression the messages are received on
+    pub topic: String,
+    // Protobuf message type of the payload, e.g. BatteryData. Without it the
+    // payload is decoded schemaless, which needs the json or cbor format
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub format: WireFormat,
+    pub fields: Vec<FieldMapping>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FieldMapping {
+    // Dotted path of the field in the message, e.g. front_tire.pressure
+    pub field: String,
+    // VSS path of the twin field, e.g. Vehicle.Powertrain.TractionBattery.Range
+    pub path: String,
+    // Numeric values are written as `value * scale + offset`
+    #[serde(default = "default_field_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+fn default_field_scale() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SnapshotConfig {
+    pub path: String,
+    #[serde(default)]
+    pub format: SnapshotFormat,
+    #[serde(default = "default_snapshot_interval")]
+    pub interval: u64, // in milliseconds
+}
+
+fn default_snapshot_interval() -> u64 {
+    60000
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotFormat {
+    #[default]
+    Protobuf,
+    Json,
 }
 
 #[derive(Debug, Deserialize)]
@@ -13,6 +279,55 @@ pub struct Event {
     pub name: String,
     pub topic: String,
     pub frequency: u64, // in milliseconds
+    #[serde(default)]
+    pub policy: PublishPolicy,
+    // Wire format of the published events
+    #[serde(default)]
+    pub format: WireFormat,
+    // What to do when a signal the event is built from is stale
+    #[serde(default)]
+    pub on_stale: StaleAction,
+    // Buffered events of higher priority are replayed first and dropped last
+    #[serde(default)]
+    pub priority: u8,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StaleAction {
+    // Publish the event, listing the stale signals in its envelope
+    #[default]
+    Mark,
+    // Don't publish the event until its signals are fresh again
+    Suppress,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PublishMode {
+    // Publish on every tick of `frequency`
+    #[default]
+    Periodic,
+    // Publish only when the event differs from the last published one
+    OnChange,
+}
+
+// Numeric tolerance for `on_change` events, either for every numeric field
+// or per field name (fields without an entry must match exactly)
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Deadband {
+    All(f64),
+    Fields(HashMap<String, f64>),
+}
+
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct PublishPolicy {
+    pub mode: PublishMode,
+    pub deadband: Option<Deadband>,
+    pub min_interval: Option<u64>, // in milliseconds, never publish more often
+    pub max_interval: Option<u64>, // in milliseconds, publish at least this often
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,3 +335,33 @@ pub struct Command {
     pub name: String,
     pub topic: String,
 }
+
+// Rejects `command` when `reject_if` holds for the current vehicle state, or
+// when the same command was accepted less than `cooldown` milliseconds ago
+#[derive(Debug, Deserialize, Clone)]
+pub struct CommandRule {
+    pub command: String,
+    pub reject_if: Option<RuleCondition>,
+    pub cooldown: Option<u64>, // in milliseconds
+    pub reason: Option<String>,
+}
+
+// Compares a field of the vehicle model, given as dotted path such as
+// `powertrain.traction_battery.state_of_charge.displayed`, with a value
+#[derive(Debug, Deserialize, Clone)]
+pub struct RuleCondition {
+    pub field: String,
+    pub operator: RuleOperator,
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleOperator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use common::topics::VEHICLE_TOPICS;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+// Tracks when each intra-vehicle signal was last received and flags the ones
+// that haven't been updated within their configured timeout. Signals are
+// identified by the topic they are received on
+#[derive(Debug, Clone)]
+pub struct SignalFreshness {
+    timeouts: HashMap<String, Duration>,
+    last_update: HashMap<&'static str, (Instant, DateTime<Utc>)>,
+    // Signals never received count as updated when the twin started
+    started: Instant,
+    // Stale signals that were already reported, to log transitions only once
+    reported_stale: HashSet<&'static str>,
+}
+
+impl Default for SignalFreshness {
+    fn default() -> Self {
+        Self {
+            timeouts: HashMap::new(),
+            last_update: HashMap::new(),
+            started: Instant::now(),
+            reported_stale: HashSet::new(),
+        }
+    }
+}
+
+impl SignalFreshness {
+    // `timeouts` maps intra-vehicle topics to their staleness timeout in milliseconds
+    pub fn new(timeouts: &HashMap<String, u64>) -> Result<Self, String> {
+        let timeouts = timeouts
+            .iter()
+            .map(|(signal, timeout)| {
+                if !VEHICLE_TOPICS.contains(&signal.as_str()) {
+                    return Err(format!(
+                        "Unknown signal '{}' in signal timeouts, expected one of: {}",
+                        signal,
+                        VEHICLE_TOPICS.join(", ")
+                    ));
+                }
+                if *timeout == 0 {
+                    return Err(format!("Signal '{}' must have a non-zero timeout", signal));
+                }
+                Ok((signal.clone(), Duration::from_millis(*timeout)))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(Self {
+            timeouts,
+            ..Self::default()
+        })
+    }
+
+    pub fn updated(&mut self, signal: &'static str) {
+        self.last_update
+            .insert(signal, (Instant::now(), Utc::now()));
+        if self.reported_stale.remove(signal) {
+            info!("Signal '{}' is fresh again", signal);
+        }
+    }
+
+    pub fn last_update(&self, signal: &str) -> Option<DateTime<Utc>> {
+        self.last_update
+            .get(signal)
+            .map(|(_, timestamp)| *timestamp)
+    }
+
+    // Signals without a timeout never become stale
+    pub fn is_stale(&self, signal: &str, now: Instant) -> bool {
+        let Some(timeout) = self.timeouts.get(signal) else {
+            return false;
+        };
+        let last_update = self
+            .last_update
+            .get(signal)
+            .map_or(self.started, |(instant, _)| *instant);
+        now.duration_since(last_update) > *timeout
+    }
+
+    // The stale ones among `signals`, logging signals that just became stale
+    pub fn stale_signals(&mut self, signals: &[&'static str]) -> Vec<&'static str> {
+        let now = Instant::now();
+        let stale: Vec<_> = signals
+            .iter()
+            .copied()
+            .filter(|signal| self.is_stale(signal, now))
+            .collect();
+        for signal in &stale {
+            if self.reported_stale.insert(signal) {
+                match self.last_update(signal) {
+                    Some(timestamp) => warn!(
+                        "Signal '{}' is stale, last updated at {}",
+                        signal,
+                        timestamp.to_rfc3339()
+                    ),
+                    None => warn!("Signal '{}' is stale, it was never received", signal),
+                }
+            }
+        }
+        stale
+    }
+}
@@ -1,6 +1,19 @@
 pub mod cloud_communicator;
 pub mod command_processor;
+pub mod command_rules;
+pub mod command_status;
 pub mod config;
+pub mod freshness;
+pub mod publish_policy;
+pub mod shadow;
+pub mod signal_mapping;
+pub mod snapshot;
+pub mod supervisor;
+pub mod system_state;
+pub mod telemetry_buffer;
 pub mod twin;
+pub mod twin_queryable;
+pub mod twin_sync;
+pub mod uplink;
 pub mod vehicle_state;
 pub mod vehicle_state_provider;
@@ -1,7 +1,8 @@
 use clap::Parser;
-use env_logger;
+use common::ZenohSessionConfig;
+use log::info;
 use std::fs;
-use std::sync::Arc;
+use twin_service::snapshot::SnapshotStore;
 use twin_service::twin::TwinService;
 
 #[derive(Parser, Clone, PartialEq, Eq, Hash, Debug)]
@@ -13,6 +14,14 @@ struct Args {
     // Path to the JSON5 configuration file for the initial vehicle state
     #[arg(short, long)]
     vehicle_state_config: String,
+
+    // Path to an optional JSON5 file mapping intra-vehicle signals to twin fields
+    #[arg(short, long)]
+    signal_mapping_config: Option<String>,
+
+    // Zenoh session options, overriding the ones from the twin configuration
+    #[command(flatten)]
+    zenoh: ZenohSessionConfig,
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 3)]
@@ -21,28 +30,50 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let args = Args::parse();
 
     // Read the JSON5 configuration file
-    let twin_config_str = fs::read_to_string(args.twin_config)?;
+    let twin_config_str = fs::read_to_string(&args.twin_config)?;
 
     // Parse the JSON5 into Rust structs
     let twin_service_config: twin_service::config::TwinServiceConfig =
         json5::from_str(&twin_config_str)?;
 
-    // Read the JSON5 configuration file for the initial vehicle state
-    let vehicle_state_config_str = fs::read_to_string(args.vehicle_state_config)?;
-    // Parse the JSON5 into Rust structs
-    let initial_state: vehicle_msgs::vehicle_msgs::Vehicle =
-        json5::from_str(&vehicle_state_config_str)?;
+    let signal_mapping = match &args.signal_mapping_config {
+        Some(path) => Some(json5::from_str(&fs::read_to_string(path)?)?),
+        None => None,
+    };
 
     // initialize logger
     env_logger::init();
 
-    // create a zenoh session
-    let config = zenoh::Config::default();
-    let session = Arc::new(zenoh::open(config).await.unwrap());
+    // Prefer the last snapshot of the twin over the configured initial state
+    let snapshot = twin_service_config
+        .snapshot
+        .as_ref()
+        .and_then(|config| SnapshotStore::new(config).load());
+    let initial_state: vehicle_msgs::vehicle_msgs::Vehicle = match snapshot {
+        Some(vehicle) => vehicle,
+        None => {
+            // Read the JSON5 configuration file for the initial vehicle state
+            let vehicle_state_config_str = fs::read_to_string(&args.vehicle_state_config)?;
+            // Parse the JSON5 into Rust structs
+            json5::from_str(&vehicle_state_config_str)?
+        }
+    };
 
-    TwinService::new(twin_service_config, initial_state)
-        .run(session)
+    // create a zenoh session
+    let session = twin_service_config
+        .session_config()
+        .merge(args.zenoh)
+        .open()
         .await?;
 
+    let mut twin_service =
+        TwinService::new(twin_service_config, initial_state, signal_mapping).await?;
+    let result = twin_service.run(session).await;
+
+    // Flush the final state, also when stopping because of a failure
+    twin_service.save_snapshot().await;
+    info!("Twin service stopped");
+    result?;
+
     Ok(())
 }
@@ -0,0 +1,107 @@
+use crate::config::{Deadband, PublishMode, PublishPolicy};
+use log::error;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+// Decides whether an event should be published based on its publish policy
+// and the last event that was actually published
+pub struct PublishGate {
+    mode: PublishMode,
+    deadband: Option<Deadband>,
+    min_interval: Option<Duration>,
+    max_interval: Option<Duration>,
+    last_published: Option<(Value, Instant)>,
+}
+
+impl PublishGate {
+    pub fn new(policy: &PublishPolicy) -> Self {
+        Self {
+            mode: policy.mode,
+            deadband: policy.deadband.clone(),
+            min_interval: policy.min_interval.map(Duration::from_millis),
+            max_interval: policy.max_interval.map(Duration::from_millis),
+            last_published: None,
+        }
+    }
+
+    // Returns the serialized event if it should be published now. Events that
+    // can't be serialized are never published, so they don't replace the last
+    // published value used for change detection
+    pub fn check<T: Serialize>(&self, event: &T, now: Instant) -> Option<Value> {
+        let value = match serde_json::to_value(event) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("Failed to serialize event for the publish policy: {}", e);
+                return None;
+            }
+        };
+
+        let Some((last_value, last_time)) = &self.last_published else {
+            return Some(value);
+        };
+        let elapsed = now.saturating_duration_since(*last_time);
+
+        if self.min_interval.is_some_and(|min| elapsed < min) {
+            return None;
+        }
+        if self.max_interval.is_some_and(|max| elapsed >= max) {
+            return Some(value);
+        }
+
+        match self.mode {
+            PublishMode::Periodic => Some(value),
+            PublishMode::OnChange => {
+                if self.has_changed(None, last_value, &value) {
+                    Some(value)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    // Records a successfully published event
+    pub fn published(&mut self, value: Value, now: Instant) {
+        self.last_published = Some((value, now));
+    }
+
+    fn has_changed(&self, field: Option<&str>, previous: &Value, current: &Value) -> bool {
+        match (previous, current) {
+            (Value::Number(previous), Value::Number(current)) => {
+                match (previous.as_f64(), current.as_f64()) {
+                    (Some(previous), Some(current)) => {
+                        (current - previous).abs() > self.deadband_for(field)
+                    }
+                    _ => previous != current,
+                }
+            }
+            (Value::Object(previous), Value::Object(current)) => {
+                previous.len() != current.len()
+                    || current.iter().any(|(key, current)| {
+                        previous
+                            .get(key)
+                            .is_none_or(|previous| self.has_changed(Some(key), previous, current))
+                    })
+            }
+            (Value::Array(previous), Value::Array(current)) => {
+                previous.len() != current.len()
+                    || previous
+                        .iter()
+                        .zip(current)
+                        .any(|(previous, current)| self.has_changed(field, previous, current))
+            }
+            (previous, current) => previous != current,
+        }
+    }
+
+    fn deadband_for(&self, field: Option<&str>) -> f64 {
+        match (&self.deadband, field) {
+            (Some(Deadband::All(deadband)), _) => *deadband,
+            (Some(Deadband::Fields(deadbands)), Some(field)) => {
+                deadbands.get(field).copied().unwrap_or(0.0)
+            }
+            _ => 0.0,
+        }
+    }
+}
@@ -0,0 +1,536 @@
+use crate::command_status::{generate_correlation_id, CommandRequest};
+use crate::config::{ShadowConfig, TwinServiceConfig};
+use crate::supervisor::{Criticality, Supervisor};
+use crate::vehicle_state::{LowVoltageSystemState, VehicleCommand, VehicleState};
+use common::ZenohSubscriber;
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use zenoh::bytes::Encoding;
+
+// Key expressions and timing of the shadow, resolved from the config
+#[derive(Debug, Clone)]
+pub struct ShadowPublication {
+    pub desired_key_expr: String,
+    pub state_key_expr: String,
+    pub retry_interval: Duration,
+    pub timeout: Duration,
+}
+
+impl ShadowPublication {
+    pub fn from_config(shadow: &ShadowConfig, config: &TwinServiceConfig) -> Result<Self, String> {
+        let key_expr = config.resolve_topic(&shadow.key_expr)?;
+        Ok(Self {
+            desired_key_expr: format!("{}/desired", key_expr),
+            state_key_expr: format!("{}/state", key_expr),
+            retry_interval: Duration::from_millis(shadow.retry_interval),
+            timeout: Duration::from_millis(shadow.timeout),
+        })
+    }
+}
+
+// Brings the vehicle to the state desired by the cloud by issuing commands
+// until the reported state matches it or the reconciliation times out
+pub struct Shadow {
+    state: Arc<Mutex<VehicleState>>,
+    publication: ShadowPublication,
+}
+
+impl Shadow {
+    pub fn new(state: Arc<Mutex<VehicleState>>, publication: ShadowPublication) -> Self {
+        Self { state, publication }
+    }
+
+    pub fn run(
+        &self,
+        session: Arc<zenoh::Session>,
+        command_tx: mpsc::Sender<CommandRequest>,
+        supervisor: &mut Supervisor,
+    ) {
+        let state = Arc::clone(&self.state);
+        let publication = self.publication.clone();
+        supervisor.spawn("shadow reconciler", Criticality::Optional, move || {
+            reconcile_shadow(
+                Arc::clone(&state),
+                session.clone(),
+                publication.clone(),
+                command_tx.clone(),
+            )
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShadowStatus {
+    // The reported state matches the desired one
+    Converged,
+    // Commands are issued until the reported state matches the desired one
+    Converging,
+    // The reported state did not match the desired one in time
+    TimedOut,
+}
+
+// Published on every change: the desired fields, their reported values and
+// those that still differ, as partial Vehicle JSON objects
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShadowReport {
+    pub status: ShadowStatus,
+    pub desired: Value,
+    pub reported: Value,
+    pub delta: Value,
+    // Differing fields no command can change, with the reason
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub unsupported: Map<String, Value>,
+}
+
+// The desired state and the progress towards it
+struct Reconciliation {
+    desired: Map<String, Value>,
+    status: ShadowStatus,
+    deadline: Option<Instant>,
+    // When a command was last issued for each desired field
+    issued: HashMap<String, Instant>,
+}
+
+impl Reconciliation {
+    fn new() -> Self {
+        Self {
+            desired: Map::new(),
+            status: ShadowStatus::Converged,
+            deadline: None,
+            issued: HashMap::new(),
+        }
+    }
+
+    // Merges a desired update, where null removes a field, and starts over
+    fn update(&mut self, update: Map<String, Value>, timeout: Duration) {
+        merge(&mut self.desired, update);
+        self.start(timeout);
+    }
+
+    fn start(&mut self, timeout: Duration) {
+        self.status = ShadowStatus::Converging;
+        self.deadline = Some(Instant::now() + timeout);
+        self.issued.clear();
+    }
+
+    // Compares the desired and reported state and returns the commands
+    // that bring the vehicle closer to the desired one
+    fn reconcile(
+        &mut self,
+        state: &VehicleState,
+        publication: &ShadowPublication,
+    ) -> (ShadowReport, Vec<VehicleCommand>) {
+        let vehicle = serde_json::to_value(state.vehicle()).unwrap_or_default();
+        let mut reported = Map::new();
+        let mut delta = Map::new();
+        let mut differing = Vec::new();
+        for (path, desired_value) in leaves(&self.desired) {
+            let pointer = format!("/{}", path.replace('.', "/"));
+            let reported_value = vehicle.pointer(&pointer).cloned().unwrap_or(Value::Null);
+            if !values_match(desired_value, &reported_value) {
+                insert_path(&mut delta, &path, desired_value.clone());
+                differing.push((path.clone(), desired_value.clone()));
+            }
+            insert_path(&mut reported, &path, reported_value);
+        }
+
+        let now = Instant::now();
+        if differing.is_empty() {
+            self.status = ShadowStatus::Converged;
+            self.deadline = None;
+        } else if self.status == ShadowStatus::Converged {
+            // The vehicle drifted away from the desired state
+            self.start(publication.timeout);
+        } else if self.deadline.is_some_and(|deadline| now >= deadline) {
+            warn!(
+                "Vehicle did not reach the desired state within {:?}",
+                publication.timeout
+            );
+            self.status = ShadowStatus::TimedOut;
+            self.deadline = None;
+        }
+
+        let mut commands = Vec::new();
+        let mut unsupported = Map::new();
+        for (path, desired_value) in differing {
+            match command_for(&path, &desired_value, state) {
+                Ok(command) => {
+                    let due = self
+                        .issued
+                        .get(&path)
+                        .is_none_or(|issued| now - *issued >= publication.retry_interval);
+                    if self.status == ShadowStatus::Converging && due {
+                        self.issued.insert(path, now);
+                        commands.push(command);
+                    }
+                }
+                Err(reason) => {
+                    unsupported.insert(path, Value::String(reason));
+                }
+            }
+        }
+
+        let report = ShadowReport {
+            status: self.status,
+            desired: Value::Object(self.desired.clone()),
+            reported: Value::Object(reported),
+            delta: Value::Object(delta),
+            unsupported,
+        };
+        (report, commands)
+    }
+}
+
+async fn reconcile_shadow(
+    state: Arc<Mutex<VehicleState>>,
+    session: Arc<zenoh::Session>,
+    publication: ShadowPublication,
+    command_tx: mpsc::Sender<CommandRequest>,
+) {
+    let subscriber =
+        match ZenohSubscriber::new(session.clone(), publication.desired_key_expr.clone()).await {
+            Ok(subscriber) => subscriber,
+            Err(e) => {
+                error!("Failed to create subscriber for the desired state: {:?}", e);
+                return;
+            }
+        };
+    let publisher = match session
+        .declare_publisher(publication.state_key_expr.clone())
+        .encoding(Encoding::APPLICATION_JSON)
+        .await
+    {
+        Ok(publisher) => publisher,
+        Err(e) => {
+            error!("Failed to create publisher for the shadow state: {:?}", e);
+            return;
+        }
+    };
+    info!(
+        "Reconciling the desired state from '{}', reporting on '{}'",
+        publication.desired_key_expr, publication.state_key_expr
+    );
+
+    let mut reconciliation = Reconciliation::new();
+    let mut last_report = None;
+    let mut retry = tokio::time::interval(publication.retry_interval);
+    loop {
+        tokio::select! {
+            sample = subscriber.subscriber.recv_async() => {
+                let Ok(sample) = sample else {
+                    error!("Desired state subscriber closed");
+                    return;
+                };
+                match serde_json::from_slice(&sample.payload().to_bytes()) {
+                    Ok(Value::Object(update)) => {
+                        info!("Received desired state update: {}", Value::Object(update.clone()));
+                        reconciliation.update(update, publication.timeout);
+                    }
+                    Ok(_) => {
+                        error!("Desired state must be a partial Vehicle JSON object");
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("Failed to decode desired state: {:?}", e);
+                        continue;
+                    }
+                }
+            }
+            _ = retry.tick() => {}
+        }
+
+        let (report, commands, vehicle_id) = {
+            let vehicle_state = state.lock().await;
+            let (report, commands) = reconciliation.reconcile(&vehicle_state, &publication);
+            (report, commands, vehicle_state.vehicle_id())
+        };
+
+        for command in commands {
+            let correlation_id = generate_correlation_id(&vehicle_id);
+            info!(
+                "Issuing {:?} ({}) for the desired state",
+                command, correlation_id
+            );
+            if let Err(e) = command_tx
+                .send(CommandRequest::new(correlation_id, command))
+                .await
+            {
+                error!("Failed to forward command: {:?}", e);
+                return;
+            }
+        }
+
+        if last_report.as_ref() != Some(&report) {
+            match serde_json::to_vec(&report) {
+                Ok(payload) => {
+                    if let Err(e) = publisher.put(payload).await {
+                        error!("Failed to publish shadow state: {:?}", e);
+                    }
+                }
+                Err(e) => error!("Failed to encode shadow state: {:?}", e),
+            }
+            last_report = Some(report);
+        }
+    }
+}
+
+// Command that moves the field at `path` towards the desired value
+fn command_for(
+    path: &str,
+    desired: &Value,
+    state: &VehicleState,
+) -> Result<VehicleCommand, String> {
+    let expect_bool = || {
+        desired
+            .as_bool()
+            .ok_or_else(|| format!("Expected a boolean for '{}'", path))
+    };
+    match path {
+        "body.lights.beam.low.is_on" => Ok(if expect_bool()? {
+            VehicleCommand::LightOn
+        } else {
+            VehicleCommand::LightOff
+        }),
+        "body.horn.is_active" => Ok(if expect_bool()? {
+            VehicleCommand::HornOn
+        } else {
+            VehicleCommand::HornOff
+        }),
+        "low_voltage_system_state" => {
+            let target: LowVoltageSystemState = desired
+                .as_str()
+                .ok_or_else(|| format!("Expected a string for '{}'", path))?
+                .parse()?;
+            let current = state.system_state().current();
+            system_state_command(current, target)
+                .ok_or_else(|| format!("No commands go from {} to {}", current, target))
+        }
+        _ => Err(format!("No command changes '{}'", path)),
+    }
+}
+
+// First command on the shortest path of legal transitions from `current` to
+// `target`, e.g. EngineOff then Lock from START. Only one command is issued at
+// a time, the next one once the vehicle reported the state it led to
+fn system_state_command(
+    current: LowVoltageSystemState,
+    target: LowVoltageSystemState,
+) -> Option<VehicleCommand> {
+    let steps = |from: LowVoltageSystemState| {
+        VehicleCommand::ALL.into_iter().filter_map(move |command| {
+            let next = command.target_system_state()?;
+            // ON is reached by stopping the engine from START, else by unlocking
+            let fits = match command {
+                VehicleCommand::EngineOff => from == LowVoltageSystemState::START,
+                VehicleCommand::Unlock => from != LowVoltageSystemState::START,
+                _ => true,
+            };
+            (fits && next != from && from.can_transition_to(next)).then_some((command, next))
+        })
+    };
+    // Breadth-first search, remembering the first command of every path
+    let mut visited = vec![current];
+    let mut frontier: VecDeque<_> = steps(current).collect();
+    while let Some((first, state)) = frontier.pop_front() {
+        if state == target {
+            return Some(first);
+        }
+        if !visited.contains(&state) {
+            visited.push(state);
+            frontier.extend(steps(state).map(|(_, next)| (first, next)));
+        }
+    }
+    None
+}
+
+fn merge(target: &mut Map<String, Value>, update: Map<String, Value>) {
+    for (key, value) in update {
+        match value {
+            Value::Null => {
+                target.remove(&key);
+            }
+            Value::Object(update) => {
+                let entry = target
+                    .entry(key.clone())
+                    .or_insert_with(|| Value::Object(Map::new()));
+                if !entry.is_object() {
+                    *entry = Value::Object(Map::new());
+                }
+                if let Value::Object(object) = entry {
+                    merge(object, update);
+                    if object.is_empty() {
+                        target.remove(&key);
+                    }
+                }
+            }
+            value => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+// Dotted paths and values of the non-object values of a JSON object
+fn leaves(object: &Map<String, Value>) -> Vec<(String, &Value)> {
+    let mut found = Vec::new();
+    for (key, value) in object {
+        match value {
+            Value::Object(object) => found.extend(
+                leaves(object)
+                    .into_iter()
+                    .map(|(path, value)| (format!("{}.{}", key, path), value)),
+            ),
+            value => found.push((key.clone(), value)),
+        }
+    }
+    found
+}
+
+fn insert_path(object: &mut Map<String, Value>, path: &str, value: Value) {
+    match path.split_once('.') {
+        Some((key, rest)) => {
+            let entry = object
+                .entry(key)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(child) = entry {
+                insert_path(child, rest, value);
+            }
+        }
+        None => {
+            object.insert(path.to_string(), value);
+        }
+    }
+}
+
+// Numbers are compared with a tolerance as the model stores most of them as f32
+fn values_match(desired: &Value, reported: &Value) -> bool {
+    match (desired.as_f64(), reported.as_f64()) {
+        (Some(desired), Some(reported)) => {
+            (desired - reported).abs() <= 1e-6 * desired.abs().max(1.0)
+        }
+        _ => desired == reported,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_rules::CommandRules;
+    use crate::freshness::SignalFreshness;
+    use serde_json::json;
+    use vehicle_msgs::partial_json::from_partial_value;
+
+    use LowVoltageSystemState::{ACC, LOCK, OFF, ON, START};
+
+    fn publication() -> ShadowPublication {
+        ShadowPublication {
+            desired_key_expr: "cloud/shadow/VEHICLE1VIN/desired".to_string(),
+            state_key_expr: "cloud/shadow/VEHICLE1VIN/state".to_string(),
+            retry_interval: Duration::from_secs(3600),
+            timeout: Duration::from_secs(3600),
+        }
+    }
+
+    fn desired(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(desired) => desired,
+            _ => panic!("desired state must be an object"),
+        }
+    }
+
+    #[test]
+    fn unlocks_before_starting() {
+        assert_eq!(
+            system_state_command(LOCK, START),
+            Some(VehicleCommand::Unlock)
+        );
+        assert_eq!(
+            system_state_command(ON, START),
+            Some(VehicleCommand::EngineOn)
+        );
+        assert_eq!(system_state_command(OFF, ON), Some(VehicleCommand::Unlock));
+    }
+
+    #[test]
+    fn stops_the_engine_before_locking() {
+        assert_eq!(
+            system_state_command(START, LOCK),
+            Some(VehicleCommand::EngineOff)
+        );
+        assert_eq!(
+            system_state_command(START, ON),
+            Some(VehicleCommand::EngineOff)
+        );
+        assert_eq!(system_state_command(ON, LOCK), Some(VehicleCommand::Lock));
+    }
+
+    #[test]
+    fn has_no_command_for_unreachable_targets() {
+        assert_eq!(system_state_command(ON, OFF), None);
+        assert_eq!(system_state_command(LOCK, ACC), None);
+        assert_eq!(system_state_command(START, OFF), None);
+    }
+
+    #[test]
+    fn reconciles_until_converged_or_timed_out() {
+        let state = VehicleState::default();
+        let publication = publication();
+        let mut reconciliation = Reconciliation::new();
+        let (report, commands) = reconciliation.reconcile(&state, &publication);
+        assert_eq!(report.status, ShadowStatus::Converged);
+        assert!(commands.is_empty());
+
+        let lights_on = json!({ "body": { "lights": { "beam": { "low": { "is_on": true } } } } });
+        reconciliation.update(desired(lights_on.clone()), publication.timeout);
+        let (report, commands) = reconciliation.reconcile(&state, &publication);
+        assert_eq!(report.status, ShadowStatus::Converging);
+        assert_eq!(report.delta, lights_on);
+        assert_eq!(commands, vec![VehicleCommand::LightOn]);
+
+        // The command is not repeated before the retry interval
+        let (report, commands) = reconciliation.reconcile(&state, &publication);
+        assert_eq!(report.status, ShadowStatus::Converging);
+        assert!(commands.is_empty());
+
+        reconciliation.update(Map::new(), Duration::ZERO);
+        let (report, commands) = reconciliation.reconcile(&state, &publication);
+        assert_eq!(report.status, ShadowStatus::TimedOut);
+        assert!(commands.is_empty());
+
+        // The vehicle reports the lights on
+        let state = VehicleState::new(
+            from_partial_value(lights_on.clone()).unwrap(),
+            String::new(),
+            CommandRules::default(),
+            SignalFreshness::default(),
+        );
+        let (report, commands) = reconciliation.reconcile(&state, &publication);
+        assert_eq!(report.status, ShadowStatus::Converged);
+        assert_eq!(report.reported, lights_on);
+        assert_eq!(report.delta, json!({}));
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn reports_fields_without_commands_as_unsupported() {
+        let state = VehicleState::default();
+        let publication = publication();
+        let mut reconciliation = Reconciliation::new();
+        let charge_limit =
+            json!({ "powertrain": { "traction_battery": { "charging": { "charge_limit": 80 } } } });
+        reconciliation.update(desired(charge_limit), publication.timeout);
+        let (report, commands) = reconciliation.reconcile(&state, &publication);
+        assert!(commands.is_empty());
+        assert!(report
+            .unsupported
+            .contains_key("powertrain.traction_battery.charging.charge_limit"));
+    }
+}
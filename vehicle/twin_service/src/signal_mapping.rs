@@ -0,0 +1,254 @@
+use crate::config::{FieldMapping, SignalMapping, SignalMappingConfig};
+use crate::supervisor::{Criticality, Supervisor};
+use crate::vehicle_state::VehicleState;
+use common::topics::VEHICLE_TOPICS;
+use common::{TopicMessage, WireFormat, ZenohSubscriber};
+use log::{error, info, trace};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use vehicle_msgs::battery::BatteryData;
+use vehicle_msgs::current_location::CurrentLocation;
+use vehicle_msgs::exterior::Exterior;
+use vehicle_msgs::horn::Horn;
+use vehicle_msgs::lights::Lights;
+use vehicle_msgs::powertrain::PowertrainState;
+use vehicle_msgs::speed::Speed;
+use vehicle_msgs::state::LockState;
+use vehicle_msgs::tires::Tires;
+use vehicle_msgs::trip_data::TripData;
+use vehicle_msgs::vss_path::{VssPath, SYSTEM_STATE_FIELD};
+
+type Decoder = fn(&[u8], WireFormat) -> Result<Value, Box<dyn std::error::Error + Send + Sync>>;
+
+// Intra-vehicle message types a mapping can name
+const MESSAGE_TYPES: &[&str] = &[
+    "BatteryData",
+    "CurrentLocation",
+    "Exterior",
+    "Horn",
+    "Lights",
+    "LockState",
+    "PowertrainState",
+    "Speed",
+    "Tires",
+    "TripData",
+];
+
+fn decoder(message: &str) -> Option<Decoder> {
+    let decoder: Decoder = match message {
+        "BatteryData" => decode_as::<BatteryData>,
+        "CurrentLocation" => decode_as::<CurrentLocation>,
+        "Exterior" => decode_as::<Exterior>,
+        "Horn" => decode_as::<Horn>,
+        "Lights" => decode_as::<Lights>,
+        "LockState" => decode_as::<LockState>,
+        "PowertrainState" => decode_as::<PowertrainState>,
+        "Speed" => decode_as::<Speed>,
+        "Tires" => decode_as::<Tires>,
+        "TripData" => decode_as::<TripData>,
+        _ => return None,
+    };
+    Some(decoder)
+}
+
+fn decode_as<T: TopicMessage>(
+    payload: &[u8],
+    format: WireFormat,
+) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(serde_json::to_value(format.decode::<T>(payload)?)?)
+}
+
+fn decode_schemaless(
+    payload: &[u8],
+    format: WireFormat,
+) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+    match format {
+        WireFormat::Json => Ok(serde_json::from_slice(payload)?),
+        WireFormat::Cbor => Ok(ciborium::from_reader(payload)?),
+        WireFormat::Protobuf => Err("Protobuf payloads need a message type".into()),
+    }
+}
+
+// A message field written to a twin field
+#[derive(Debug, Clone)]
+struct FieldRule {
+    pointer: String,
+    path: VssPath,
+    scale: f64,
+    offset: f64,
+}
+
+impl FieldRule {
+    fn from_config(field: &FieldMapping) -> Result<Self, String> {
+        if field.field.is_empty() {
+            return Err(format!("Missing message field for '{}'", field.path));
+        }
+        let path: VssPath = field.path.parse()?;
+        // Mapped values would bypass the legal transitions of the state machine
+        if path.fields().first().map(String::as_str) == Some(SYSTEM_STATE_FIELD) {
+            return Err(format!(
+                "'{}' can't be mapped, the system state is only changed through the lock_state topic",
+                field.path
+            ));
+        }
+        Ok(Self {
+            pointer: format!("/{}", field.field.replace('.', "/")),
+            path,
+            scale: field.scale,
+            offset: field.offset,
+        })
+    }
+
+    fn convert(&self, value: &Value) -> Result<Value, String> {
+        if self.scale == 1.0 && self.offset == 0.0 {
+            return Ok(value.clone());
+        }
+        let number = value.as_f64().ok_or_else(|| {
+            format!(
+                "Can't scale non-numeric value {} for '{}'",
+                value, self.path
+            )
+        })?;
+        let converted = number * self.scale + self.offset;
+        // Whole numbers stay integers so that they fit integer twin fields
+        if converted.fract() == 0.0 && converted.abs() < i64::MAX as f64 {
+            Ok(json!(converted as i64))
+        } else {
+            Ok(json!(converted))
+        }
+    }
+}
+
+// How the messages of one intra-vehicle topic are written to the twin
+#[derive(Debug, Clone)]
+pub struct SignalRule {
+    key_expr: String,
+    format: WireFormat,
+    decode: Decoder,
+    fields: Vec<FieldRule>,
+    // Topic tracked by the signal freshness, if it is a known intra-vehicle one
+    signal: Option<&'static str>,
+}
+
+impl SignalRule {
+    pub fn from_config(mapping: &SignalMapping) -> Result<Self, String> {
+        let decode = match &mapping.message {
+            Some(message) => decoder(message).ok_or_else(|| {
+                format!(
+                    "Unknown message type '{}' for '{}', expected one of: {}",
+                    message,
+                    mapping.topic,
+                    MESSAGE_TYPES.join(", ")
+                )
+            })?,
+            None if mapping.format == WireFormat::Protobuf => {
+                return Err(format!(
+                    "Mapping for '{}' needs a message type or the json or cbor format",
+                    mapping.topic
+                ))
+            }
+            None => decode_schemaless,
+        };
+        if mapping.fields.is_empty() {
+            return Err(format!("Mapping for '{}' has no fields", mapping.topic));
+        }
+        Ok(Self {
+            key_expr: mapping.topic.clone(),
+            format: mapping.format,
+            decode,
+            fields: mapping
+                .fields
+                .iter()
+                .map(FieldRule::from_config)
+                .collect::<Result<_, _>>()?,
+            signal: VEHICLE_TOPICS
+                .iter()
+                .find(|topic| **topic == mapping.topic)
+                .copied(),
+        })
+    }
+
+    // Writes the mapped fields of a received payload to the twin. Fields
+    // missing from schemaless messages are left unchanged
+    pub fn apply(
+        &self,
+        payload: &[u8],
+        state: &mut VehicleState,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let message = (self.decode)(payload, self.format)?;
+        let mut values = Vec::with_capacity(self.fields.len());
+        for field in &self.fields {
+            match message.pointer(&field.pointer) {
+                Some(value) => values.push((field.path.clone(), field.convert(value)?)),
+                None => trace!("No '{}' in message on '{}'", field.pointer, self.key_expr),
+            }
+        }
+        state.set_paths(values)
+    }
+}
+
+// Writes intra-vehicle messages to the twin as declared in the mapping file
+pub struct SignalMapper {
+    state: Arc<Mutex<VehicleState>>,
+    rules: Vec<SignalRule>,
+}
+
+impl SignalMapper {
+    pub fn new(
+        state: Arc<Mutex<VehicleState>>,
+        config: &SignalMappingConfig,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            state,
+            rules: config
+                .signals
+                .iter()
+                .map(SignalRule::from_config)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn run(&self, session: Arc<zenoh::Session>, supervisor: &mut Supervisor) {
+        for rule in &self.rules {
+            let state = Arc::clone(&self.state);
+            let session = session.clone();
+            let rule = rule.clone();
+            supervisor.spawn(
+                format!("{} mapped signal subscriber", rule.key_expr),
+                Criticality::Critical,
+                move || ingest(Arc::clone(&state), session.clone(), rule.clone()),
+            );
+        }
+    }
+}
+
+async fn ingest(state: Arc<Mutex<VehicleState>>, session: Arc<zenoh::Session>, rule: SignalRule) {
+    let subscriber = match ZenohSubscriber::new(session, rule.key_expr.clone()).await {
+        Ok(subscriber) => subscriber,
+        Err(e) => {
+            error!(
+                "Failed to create subscriber for mapped signal '{}': {:?}",
+                rule.key_expr, e
+            );
+            return;
+        }
+    };
+    info!(
+        "Mapping {} fields of '{}' to the twin",
+        rule.fields.len(),
+        rule.key_expr
+    );
+
+    while let Ok(sample) = subscriber.subscriber.recv_async().await {
+        let mut state = state.lock().await;
+        match rule.apply(&sample.payload().to_bytes(), &mut state) {
+            Ok(_) => {
+                if let Some(signal) = rule.signal {
+                    state.freshness.updated(signal);
+                }
+            }
+            Err(e) => error!("Failed to map '{}' to the twin: {}", rule.key_expr, e),
+        }
+    }
+}
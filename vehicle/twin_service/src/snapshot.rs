@@ -0,0 +1,105 @@
+use crate::config::{SnapshotConfig, SnapshotFormat};
+use crate::vehicle_state::VehicleState;
+use log::{error, info, warn};
+use prost::Message;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use vehicle_msgs::vehicle_msgs::Vehicle;
+
+// Stores snapshots of the digital twin in a local file so that accumulated
+// values survive a restart of the service
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    path: PathBuf,
+    format: SnapshotFormat,
+}
+
+impl SnapshotStore {
+    pub fn new(config: &SnapshotConfig) -> Self {
+        Self {
+            path: PathBuf::from(&config.path),
+            format: config.format,
+        }
+    }
+
+    // Returns the stored snapshot, or None if it is missing or can't be decoded
+    pub fn load(&self) -> Option<Vehicle> {
+        let bytes = match std::fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("No twin snapshot at {}", self.path.display());
+                return None;
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to read twin snapshot {}: {}",
+                    self.path.display(),
+                    e
+                );
+                return None;
+            }
+        };
+
+        match self.decode(&bytes) {
+            Ok(vehicle) => {
+                info!("Restored twin state from {}", self.path.display());
+                Some(vehicle)
+            }
+            Err(e) => {
+                warn!(
+                    "Ignoring corrupt twin snapshot {}: {}",
+                    self.path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    // Writes to a temporary file first so that a crash never leaves a partial snapshot
+    pub async fn save(
+        &self,
+        vehicle: &Vehicle,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let bytes = match self.format {
+            SnapshotFormat::Protobuf => vehicle.encode_to_vec(),
+            SnapshotFormat::Json => serde_json::to_vec_pretty(vehicle)?,
+        };
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        tokio::fs::write(&tmp_path, bytes).await?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+        Ok(())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Vehicle, Box<dyn std::error::Error + Send + Sync>> {
+        if bytes.is_empty() {
+            return Err("snapshot is empty".into());
+        }
+        match self.format {
+            SnapshotFormat::Protobuf => Ok(Vehicle::decode(bytes)?),
+            SnapshotFormat::Json => Ok(serde_json::from_slice(bytes)?),
+        }
+    }
+}
+
+// Saves the vehicle state every `interval`
+pub async fn save_periodically(
+    state: Arc<Mutex<VehicleState>>,
+    store: SnapshotStore,
+    interval: Duration,
+) {
+    let mut interval = tokio::time::interval(interval);
+    // The first tick completes immediately, the restored state is already on disk
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let vehicle = state.lock().await.vehicle().clone();
+        if let Err(e) = store.save(&vehicle).await {
+            error!("Failed to save twin snapshot: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use crate::config::SupervisorConfig;
+use common::os_signal;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use log::{error, info, warn};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::{JoinHandle, JoinSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Criticality {
+    // The service can't work without the task and stops if it can't be recovered
+    Critical,
+    // The service keeps running once the task gave up
+    Optional,
+}
+
+// Resolves once the supervisor is shutting down. Tasks are cancelled at their
+// next await point, this is for work that must finish before stopping
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal(watch::Receiver<bool>);
+
+impl ShutdownSignal {
+    pub async fn wait(&mut self) {
+        // An error means the supervisor is gone, which is a shutdown as well
+        let _ = self.0.wait_for(|stopping| *stopping).await;
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+// Runs the service tasks, restarts the ones that stop with an exponential
+// backoff and cancels all of them on SIGINT/SIGTERM
+pub struct Supervisor {
+    config: SupervisorConfig,
+    shutdown_tx: watch::Sender<bool>,
+    tasks: JoinSet<Result<(), String>>,
+}
+
+impl Supervisor {
+    pub fn new(config: SupervisorConfig) -> Self {
+        let (shutdown_tx, _) = watch::channel(false);
+        Self {
+            config,
+            shutdown_tx,
+            tasks: JoinSet::new(),
+        }
+    }
+
+    pub fn shutdown_signal(&self) -> ShutdownSignal {
+        ShutdownSignal(self.shutdown_tx.subscribe())
+    }
+
+    // Supervises a task created by `factory`, which is called again to
+    // restart the task whenever it stops or panics
+    pub fn spawn<F, Fut>(&mut self, name: impl Into<String>, criticality: Criticality, factory: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut factory = factory;
+        self.supervise(name.into(), criticality, move || Some(factory().boxed()));
+    }
+
+    // Supervises a task that owns state it can't be recreated from, such as
+    // the receiving end of a channel. It is critical as it can't be restarted
+    pub fn spawn_once<Fut>(&mut self, name: impl Into<String>, future: Fut)
+    where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut future = Some(future.boxed());
+        self.supervise(name.into(), Criticality::Critical, move || future.take());
+    }
+
+    fn supervise<F>(&mut self, name: String, criticality: Criticality, mut factory: F)
+    where
+        F: FnMut() -> Option<BoxFuture<'static, ()>> + Send + 'static,
+    {
+        let config = self.config.clone();
+        let mut shutdown = self.shutdown_signal();
+        self.tasks.spawn(async move {
+            let mut restarts = 0;
+            let mut backoff = Duration::from_millis(config.initial_backoff);
+            let max_backoff = Duration::from_millis(config.max_backoff);
+            let mut next = factory();
+            while let Some(task) = next.take() {
+                let started = Instant::now();
+                let mut task_shutdown = shutdown.clone();
+                let mut task = AbortOnDrop(tokio::spawn(async move {
+                    tokio::select! {
+                        _ = task => {}
+                        _ = task_shutdown.wait() => {}
+                    }
+                }));
+                let result = (&mut task.0).await;
+                if shutdown.is_shutting_down() {
+                    return Ok(());
+                }
+
+                match result {
+                    Err(e) if e.is_panic() => error!("Task '{}' panicked", name),
+                    _ => warn!("Task '{}' stopped unexpectedly", name),
+                }
+
+                // A task that ran for a while before failing starts over with a fresh budget
+                if started.elapsed() > max_backoff {
+                    restarts = 0;
+                    backoff = Duration::from_millis(config.initial_backoff);
+                }
+                if restarts >= config.max_restarts {
+                    break;
+                }
+                // Tasks that can't be recreated fail right away
+                next = factory();
+                if next.is_none() {
+                    break;
+                }
+                restarts += 1;
+                warn!(
+                    "Restarting task '{}' in {:?} (attempt {}/{})",
+                    name, backoff, restarts, config.max_restarts
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = shutdown.wait() => return Ok(()),
+                }
+                backoff = (backoff * 2).min(max_backoff);
+            }
+
+            match criticality {
+                Criticality::Critical => Err(name),
+                Criticality::Optional => {
+                    error!("Giving up on task '{}'", name);
+                    Ok(())
+                }
+            }
+        });
+    }
+
+    // Runs until a shutdown signal is received or a critical task can't be
+    // recovered, then cancels all tasks. Returns an error in the latter case
+    pub async fn run(mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let result = tokio::select! {
+            signal = os_signal() => {
+                info!("Received {}, shutting down", signal);
+                Ok(())
+            }
+            name = critical_failure(&mut self.tasks) => {
+                error!("Critical task '{}' could not be recovered, shutting down", name);
+                Err(format!("Critical task '{}' could not be recovered", name).into())
+            }
+        };
+
+        let _ = self.shutdown_tx.send(true);
+        let shutdown_timeout = Duration::from_millis(self.config.shutdown_timeout);
+        let stopped = tokio::time::timeout(shutdown_timeout, async {
+            while self.tasks.join_next().await.is_some() {}
+        })
+        .await;
+        if stopped.is_err() {
+            warn!(
+                "Tasks did not stop within {:?}, aborting them",
+                shutdown_timeout
+            );
+            self.tasks.shutdown().await;
+        }
+
+        result
+    }
+}
+
+// Aborts the task when the supervising task is aborted on shutdown, which
+// would otherwise just detach it
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+// Resolves with the name of the first critical task that gave up
+async fn critical_failure(tasks: &mut JoinSet<Result<(), String>>) -> String {
+    while let Some(result) = tasks.join_next().await {
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(name)) => return name,
+            Err(e) => return format!("supervisor ({})", e),
+        }
+    }
+    // Nothing left to supervise, wait for a shutdown signal
+    std::future::pending().await
+}
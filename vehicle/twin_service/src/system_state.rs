@@ -0,0 +1,164 @@
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use vehicle_msgs::state::State;
+
+// Number of transitions kept in the state machine history
+const TRANSITION_HISTORY_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LowVoltageSystemState {
+    UNDEFINED,
+    LOCK,
+    OFF,
+    ACC,
+    ON,
+    START,
+}
+
+impl LowVoltageSystemState {
+    // States reachable from this one. UNDEFINED can move anywhere so that the
+    // first reported state is always accepted, but nothing goes back to it
+    pub fn can_transition_to(&self, next: LowVoltageSystemState) -> bool {
+        use LowVoltageSystemState::*;
+        if *self == next {
+            return true;
+        }
+        match self {
+            UNDEFINED => next != UNDEFINED,
+            // Remote unlock switches the ignition on
+            LOCK => matches!(next, OFF | ON),
+            OFF => matches!(next, LOCK | ACC | ON),
+            ACC => matches!(next, OFF | ON),
+            // Remote lock switches the ignition off
+            ON => matches!(next, LOCK | OFF | ACC | START),
+            START => matches!(next, ON | OFF),
+        }
+    }
+}
+
+impl fmt::Display for LowVoltageSystemState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            LowVoltageSystemState::UNDEFINED => "UNDEFINED",
+            LowVoltageSystemState::LOCK => "LOCK",
+            LowVoltageSystemState::OFF => "OFF",
+            LowVoltageSystemState::ACC => "ACC",
+            LowVoltageSystemState::ON => "ON",
+            LowVoltageSystemState::START => "START",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for LowVoltageSystemState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "UNDEFINED" => Ok(LowVoltageSystemState::UNDEFINED),
+            "LOCK" => Ok(LowVoltageSystemState::LOCK),
+            "OFF" => Ok(LowVoltageSystemState::OFF),
+            "ACC" => Ok(LowVoltageSystemState::ACC),
+            "ON" => Ok(LowVoltageSystemState::ON),
+            "START" => Ok(LowVoltageSystemState::START),
+            _ => Err(format!("Unknown low voltage system state '{}'", s)),
+        }
+    }
+}
+
+impl From<State> for LowVoltageSystemState {
+    fn from(state: State) -> Self {
+        match state {
+            State::Undefined => LowVoltageSystemState::UNDEFINED,
+            State::Lock => LowVoltageSystemState::LOCK,
+            State::Off => LowVoltageSystemState::OFF,
+            State::Acc => LowVoltageSystemState::ACC,
+            State::On => LowVoltageSystemState::ON,
+            State::Start => LowVoltageSystemState::START,
+        }
+    }
+}
+
+impl From<LowVoltageSystemState> for State {
+    fn from(state: LowVoltageSystemState) -> Self {
+        match state {
+            LowVoltageSystemState::UNDEFINED => State::Undefined,
+            LowVoltageSystemState::LOCK => State::Lock,
+            LowVoltageSystemState::OFF => State::Off,
+            LowVoltageSystemState::ACC => State::Acc,
+            LowVoltageSystemState::ON => State::On,
+            LowVoltageSystemState::START => State::Start,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemStateTransition {
+    pub from: LowVoltageSystemState,
+    pub to: LowVoltageSystemState,
+    pub timestamp: DateTime<Utc>,
+}
+
+// Tracks the low voltage system state and only allows legal transitions
+#[derive(Debug, Clone)]
+pub struct SystemStateMachine {
+    current: LowVoltageSystemState,
+    transitions: VecDeque<SystemStateTransition>,
+}
+
+impl Default for SystemStateMachine {
+    fn default() -> Self {
+        Self::new(LowVoltageSystemState::UNDEFINED)
+    }
+}
+
+impl SystemStateMachine {
+    pub fn new(initial: LowVoltageSystemState) -> Self {
+        Self {
+            current: initial,
+            transitions: VecDeque::with_capacity(TRANSITION_HISTORY_SIZE),
+        }
+    }
+
+    pub fn current(&self) -> LowVoltageSystemState {
+        self.current
+    }
+
+    // Most recent transitions, oldest first
+    pub fn transitions(&self) -> impl Iterator<Item = &SystemStateTransition> {
+        self.transitions.iter()
+    }
+
+    pub fn check_transition(&self, next: LowVoltageSystemState) -> Result<(), String> {
+        if self.current.can_transition_to(next) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Illegal system state transition from {} to {}",
+                self.current, next
+            ))
+        }
+    }
+
+    // Moves to `next` if the transition is legal. Staying in the same state is
+    // accepted but not recorded as a transition
+    pub fn transition(&mut self, next: LowVoltageSystemState) -> Result<(), String> {
+        self.check_transition(next)?;
+        if next == self.current {
+            return Ok(());
+        }
+
+        if self.transitions.len() == TRANSITION_HISTORY_SIZE {
+            self.transitions.pop_front();
+        }
+        self.transitions.push_back(SystemStateTransition {
+            from: self.current,
+            to: next,
+            timestamp: Utc::now(),
+        });
+        self.current = next;
+        Ok(())
+    }
+}
@@ -0,0 +1,371 @@
+use crate::config::{BufferConfig, DropPolicy};
+use common::EncodedMessage;
+use log::{error, info, trace, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::fs;
+use zenoh::pubsub::Publisher;
+
+// Position of a buffered event: higher priorities first, then in the order
+// the events were stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EntryId {
+    inverted_priority: u8,
+    sequence: u64,
+}
+
+impl EntryId {
+    fn new(priority: u8, sequence: u64) -> Self {
+        Self {
+            inverted_priority: u8::MAX - priority,
+            sequence,
+        }
+    }
+
+    pub fn priority(&self) -> u8 {
+        u8::MAX - self.inverted_priority
+    }
+
+    fn file_name(&self) -> String {
+        format!("{:03}-{:020}.cbor", self.inverted_priority, self.sequence)
+    }
+
+    fn from_file_name(name: &str) -> Option<Self> {
+        let (inverted_priority, sequence) = name.strip_suffix(".cbor")?.split_once('-')?;
+        Some(Self {
+            inverted_priority: inverted_priority.parse().ok()?,
+            sequence: sequence.parse().ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    key_expr: String,
+    size: u64,
+    // False while the event is still being written to its file
+    stored: bool,
+}
+
+// Buffered events by position, with their total size
+#[derive(Debug, Default)]
+struct Index {
+    entries: BTreeMap<EntryId, Entry>,
+    size: u64,
+    next_sequence: u64,
+}
+
+impl Index {
+    fn insert(&mut self, id: EntryId, key_expr: String, size: u64, stored: bool) {
+        self.next_sequence = self.next_sequence.max(id.sequence + 1);
+        self.size += size;
+        self.entries.insert(
+            id,
+            Entry {
+                key_expr,
+                size,
+                stored,
+            },
+        );
+    }
+
+    fn remove(&mut self, id: EntryId) -> bool {
+        match self.entries.remove(&id) {
+            Some(entry) => {
+                self.size -= entry.size;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Bounded on-disk queue of cloud events that couldn't be published, one file
+// per event so that a crash loses at most the event being written. The index
+// is only locked while it is updated, files are read and written without
+// holding the lock so that publishers don't wait for each other's disk I/O
+#[derive(Debug)]
+pub struct TelemetryBuffer {
+    dir: PathBuf,
+    max_size: u64,
+    drop_policy: DropPolicy,
+    index: Mutex<Index>,
+}
+
+impl TelemetryBuffer {
+    // Opens the buffer directory, picking up events stored before a restart
+    pub async fn open(
+        config: &BufferConfig,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let dir = PathBuf::from(&config.path);
+        fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| format!("Failed to create telemetry buffer {}: {}", dir.display(), e))?;
+
+        let mut index = Index::default();
+        let mut files = fs::read_dir(&dir).await?;
+        while let Some(file) = files.next_entry().await? {
+            let path = file.path();
+            // Left by a crash while writing, the event never made it into the buffer
+            if path.extension().is_some_and(|extension| extension == "tmp") {
+                warn!("Removing partially written event {}", path.display());
+                fs::remove_file(&path).await?;
+                continue;
+            }
+            let id = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(EntryId::from_file_name);
+            let Some(id) = id else {
+                continue;
+            };
+            match read_file(&path).await {
+                Ok(message) => {
+                    let size = file.metadata().await?.len();
+                    index.insert(id, message.key_expr, size, true);
+                }
+                Err(e) => {
+                    warn!("Removing corrupt buffered event {}: {}", path.display(), e);
+                    fs::remove_file(&path).await?;
+                }
+            }
+        }
+        if !index.entries.is_empty() {
+            info!(
+                "Found {} buffered cloud events ({} bytes)",
+                index.entries.len(),
+                index.size
+            );
+        }
+        Ok(Self {
+            dir,
+            max_size: config.max_size,
+            drop_policy: config.drop_policy,
+            index: Mutex::new(index),
+        })
+    }
+
+    fn path(&self, id: EntryId) -> PathBuf {
+        self.dir.join(id.file_name())
+    }
+
+    // A poisoned lock only means a task panicked, the index stays usable
+    fn index(&self) -> MutexGuard<'_, Index> {
+        self.index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn len(&self) -> usize {
+        self.index().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index().entries.is_empty()
+    }
+
+    // Whether events of `key_expr` are waiting, newer ones must queue up behind them
+    pub fn has_pending(&self, key_expr: &str) -> bool {
+        self.index()
+            .entries
+            .values()
+            .any(|entry| entry.key_expr == key_expr)
+    }
+
+    // Stores an event, dropping events according to the drop policy if the
+    // buffer is full. Returns false if the event itself was dropped
+    pub async fn push(
+        &self,
+        priority: u8,
+        message: &EncodedMessage,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(message, &mut bytes)?;
+        let size = bytes.len() as u64;
+        if size > self.max_size {
+            return Err(format!(
+                "Event of {} bytes exceeds the buffer size of {} bytes",
+                size, self.max_size
+            )
+            .into());
+        }
+
+        let (id, dropped) = self.reserve(priority, &message.key_expr, size);
+        for dropped in dropped {
+            if let Err(e) = fs::remove_file(self.path(dropped)).await {
+                error!("Failed to remove dropped event {:?}: {}", dropped, e);
+            }
+        }
+        let Some(id) = id else {
+            return Ok(false);
+        };
+
+        // Write to a temporary file first so that a crash never leaves a partial event
+        let path = self.path(id);
+        let tmp_path = path.with_extension("tmp");
+        let written = match fs::write(&tmp_path, bytes).await {
+            Ok(_) => fs::rename(&tmp_path, &path).await,
+            Err(e) => Err(e),
+        };
+        let mut index = self.index();
+        if let Err(e) = written {
+            index.remove(id);
+            return Err(e.into());
+        }
+        if let Some(entry) = index.entries.get_mut(&id) {
+            entry.stored = true;
+        }
+        Ok(true)
+    }
+
+    // Makes room for and adds an event that is about to be written. Returns
+    // its id, None if it is dropped itself, and the events dropped for it
+    fn reserve(&self, priority: u8, key_expr: &str, size: u64) -> (Option<EntryId>, Vec<EntryId>) {
+        let mut index = self.index();
+        let mut dropped = Vec::new();
+        while index.size + size > self.max_size {
+            // Only events of the same or a lower priority make room for new
+            // ones, starting with the oldest event of the lowest priority
+            let lowest = index
+                .entries
+                .keys()
+                .next_back()
+                .map(|id| id.priority())
+                .filter(|lowest| *lowest <= priority);
+            let oldest = lowest.and_then(|lowest| {
+                index
+                    .entries
+                    .iter()
+                    .find(|(id, entry)| id.priority() == lowest && entry.stored)
+                    .map(|(id, _)| *id)
+            });
+            match (self.drop_policy, oldest) {
+                (DropPolicy::Oldest, Some(oldest)) => {
+                    warn!("Telemetry buffer full, dropping oldest event {:?}", oldest);
+                    index.remove(oldest);
+                    dropped.push(oldest);
+                }
+                _ => {
+                    warn!(
+                        "Telemetry buffer full, dropping new event for '{}'",
+                        key_expr
+                    );
+                    return (None, dropped);
+                }
+            }
+        }
+        let id = EntryId::new(priority, index.next_sequence);
+        index.insert(id, key_expr.to_string(), size, false);
+        (Some(id), dropped)
+    }
+
+    // Buffered events in replay order. Events being written and the ones
+    // after them with the same key expression are left for the next replay
+    pub fn pending(&self) -> Vec<(EntryId, String)> {
+        let index = self.index();
+        let mut writing: Vec<&str> = Vec::new();
+        let mut pending = Vec::new();
+        for (id, entry) in &index.entries {
+            if !entry.stored {
+                writing.push(&entry.key_expr);
+            } else if !writing.contains(&entry.key_expr.as_str()) {
+                pending.push((*id, entry.key_expr.clone()));
+            }
+        }
+        pending
+    }
+
+    pub async fn read(
+        &self,
+        id: EntryId,
+    ) -> Result<EncodedMessage, Box<dyn std::error::Error + Send + Sync>> {
+        read_file(&self.path(id)).await
+    }
+
+    pub async fn remove(
+        &self,
+        id: EntryId,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // The event may have been dropped to make room for a newer one meanwhile
+        if self.index().remove(id) {
+            fs::remove_file(self.path(id)).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn read_file(
+    path: &Path,
+) -> Result<EncodedMessage, Box<dyn std::error::Error + Send + Sync>> {
+    let bytes = fs::read(path).await?;
+    Ok(ciborium::from_reader(bytes.as_slice())?)
+}
+// Replays buffered events once their key expression has subscribers again,
+// keeping the order of the events of each key expression
+pub async fn replay_buffered_events(
+    buffer: Arc<TelemetryBuffer>,
+    session: Arc<zenoh::Session>,
+    retry_interval: Duration,
+) {
+    let mut publishers: HashMap<String, Publisher<'static>> = HashMap::new();
+    loop {
+        tokio::time::sleep(retry_interval).await;
+        let pending = buffer.pending();
+        let mut blocked: Vec<String> = Vec::new();
+        let mut replayed = 0;
+        for (id, key_expr) in pending {
+            if blocked.contains(&key_expr) {
+                continue;
+            }
+            if !publishers.contains_key(&key_expr) {
+                match session.declare_publisher(key_expr.clone()).await {
+                    Ok(publisher) => {
+                        publishers.insert(key_expr.clone(), publisher);
+                    }
+                    Err(e) => {
+                        error!("Failed to create publisher for '{}': {:?}", key_expr, e);
+                        blocked.push(key_expr);
+                        continue;
+                    }
+                }
+            }
+            let connected = publishers[&key_expr]
+                .matching_status()
+                .await
+                .map(|status| status.matching())
+                .unwrap_or(false);
+            if !connected {
+                blocked.push(key_expr);
+                continue;
+            }
+
+            let result = match buffer.read(id).await {
+                Ok(message) => message.put(&session).await,
+                Err(e) => {
+                    warn!("Dropping unreadable buffered event {:?}: {}", id, e);
+                    Ok(())
+                }
+            };
+            match result {
+                Ok(_) => {
+                    trace!("Replayed buffered event {:?} to '{}'", id, key_expr);
+                    replayed += 1;
+                    if let Err(e) = buffer.remove(id).await {
+                        error!("Failed to remove buffered event {:?}: {:?}", id, e);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to replay buffered event to '{}': {:?}", key_expr, e);
+                    blocked.push(key_expr);
+                }
+            }
+        }
+        if replayed > 0 {
+            info!(
+                "Replayed {} buffered cloud events, {} left",
+                replayed,
+                buffer.len()
+            );
+        }
+    }
+}
@@ -1,67 +1,163 @@
 use crate::cloud_communicator::CloudCommunicator;
 use crate::command_processor::CommandProcessor;
-use crate::config::TwinServiceConfig;
-use crate::vehicle_state::{VehicleCommand, VehicleState};
+use crate::command_rules::CommandRules;
+use crate::command_status::{CommandReporter, CommandRequest};
+use crate::config::{SignalMappingConfig, TwinServiceConfig};
+use crate::freshness::SignalFreshness;
+use crate::shadow::{Shadow, ShadowPublication};
+use crate::signal_mapping::SignalMapper;
+use crate::snapshot::{save_periodically, SnapshotStore};
+use crate::supervisor::{Criticality, Supervisor};
+use crate::twin_queryable::TwinQueryable;
+use crate::twin_sync::{TwinSync, TwinSyncPublication};
+use crate::vehicle_state::VehicleState;
 use crate::vehicle_state_provider::VehicleStateProvider;
+use log::error;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
+use vehicle_msgs::vehicle_command_status::CommandStatusEvent;
 use vehicle_msgs::vehicle_msgs::Vehicle;
 
 pub struct TwinService {
     vehicle_state_provider: VehicleStateProvider,
+    signal_mapper: Option<SignalMapper>,
     cloud_communicator: CloudCommunicator,
     command_processor: CommandProcessor,
+    twin_queryable: TwinQueryable,
+    twin_sync: Option<TwinSync>,
+    shadow: Option<Shadow>,
+    snapshot_store: Option<SnapshotStore>,
+    state: Arc<Mutex<VehicleState>>,
     config: TwinServiceConfig,
 }
 
 impl TwinService {
-    pub fn new(config: TwinServiceConfig, initial_state: Vehicle) -> Self {
-        let state = Arc::new(Mutex::new(VehicleState {
-            vehicle: initial_state,
-            vehicle_id: config.vehicle_id.clone(),
-        }));
+    pub async fn new(
+        config: TwinServiceConfig,
+        initial_state: Vehicle,
+        signal_mapping: Option<SignalMappingConfig>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let state = Arc::new(Mutex::new(VehicleState::new(
+            initial_state,
+            config.vehicle_id.clone(),
+            CommandRules::new(&config.command_rules)?,
+            SignalFreshness::new(&config.signal_timeouts)?,
+        )));
 
         // TODO: properly use config to set up service's components
         let vehicle_state_provider = VehicleStateProvider::new(Arc::clone(&state));
-        let cloud_communicator = CloudCommunicator::new(Arc::clone(&state));
-        let command_processor = CommandProcessor::new(Arc::clone(&state));
+        let signal_mapper = match &signal_mapping {
+            Some(signal_mapping) => Some(SignalMapper::new(Arc::clone(&state), signal_mapping)?),
+            None => None,
+        };
+        let cloud_communicator = CloudCommunicator::new(Arc::clone(&state), &config).await?;
+        let command_processor = CommandProcessor::new(
+            Arc::clone(&state),
+            Duration::from_millis(config.command_timeout),
+        );
 
-        Self {
+        let twin_queryable = TwinQueryable::new(
+            Arc::clone(&state),
+            config.resolve_topic(&config.twin_key_expr)?,
+        );
+
+        let twin_sync = match &config.sync {
+            Some(sync) => Some(TwinSync::new(
+                Arc::clone(&state),
+                TwinSyncPublication::from_config(sync, &config)?,
+            )),
+            None => None,
+        };
+
+        let shadow = match &config.shadow {
+            Some(shadow) => Some(Shadow::new(
+                Arc::clone(&state),
+                ShadowPublication::from_config(shadow, &config)?,
+            )),
+            None => None,
+        };
+
+        Ok(Self {
             vehicle_state_provider,
+            signal_mapper,
             cloud_communicator,
             command_processor,
+            twin_queryable,
+            twin_sync,
+            shadow,
+            snapshot_store: config.snapshot.as_ref().map(SnapshotStore::new),
+            state,
             config,
-        }
+        })
     }
 
+    // Runs the service until SIGINT/SIGTERM or until a critical task can't be recovered
     pub async fn run(
         &mut self,
         session: Arc<zenoh::Session>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut supervisor = Supervisor::new(self.config.supervisor.clone());
+
         // Run the vehicle state provider to listen to vehicle signals
-        let mut tasks = self.vehicle_state_provider.run(session.clone()).await?;
+        self.vehicle_state_provider
+            .run(session.clone(), &mut supervisor);
+
+        // Write the signals declared in the mapping file to the twin
+        if let Some(signal_mapper) = &self.signal_mapper {
+            signal_mapper.run(session.clone(), &mut supervisor);
+        }
+
+        let (command_tx, command_rx) = mpsc::channel::<CommandRequest>(100);
+        let (status_tx, status_rx) = mpsc::channel::<CommandStatusEvent>(100);
+        let reporter = CommandReporter::new(self.config.vehicle_id.clone(), status_tx);
+
+        // Reconcile the state desired by the cloud through commands
+        if let Some(shadow) = &self.shadow {
+            shadow.run(session.clone(), command_tx.clone(), &mut supervisor);
+        }
 
-        let (command_tx, command_rx) = mpsc::channel::<VehicleCommand>(100);
         // Run the cloud communicator to send state and receive commands
-        let mut cloud_tasks = self
-            .cloud_communicator
-            .run(session.clone(), command_tx)
-            .await?;
+        self.cloud_communicator.run(
+            session.clone(),
+            command_tx,
+            reporter.clone(),
+            status_rx,
+            &mut supervisor,
+        );
 
         // Task to process cloud commands
-        let command_processing_task = self.command_processor.run(session.clone(), command_rx);
+        self.command_processor
+            .run(session.clone(), command_rx, reporter, &mut supervisor);
+
+        // Answer on-demand reads of the digital twin
+        self.twin_queryable.run(session.clone(), &mut supervisor);
+
+        // Mirror the whole twin to the cloud
+        if let Some(twin_sync) = &self.twin_sync {
+            twin_sync.run(session.clone(), &mut supervisor);
+        }
 
-        // Collect all tasks and await them
-        tasks.append(&mut cloud_tasks);
-        tasks.push(command_processing_task);
+        if let (Some(store), Some(snapshot)) = (&self.snapshot_store, &self.config.snapshot) {
+            let state = Arc::clone(&self.state);
+            let store = store.clone();
+            let interval = Duration::from_millis(snapshot.interval);
+            supervisor.spawn("twin snapshots", Criticality::Optional, move || {
+                save_periodically(Arc::clone(&state), store.clone(), interval)
+            });
+        }
 
-        // Await all tasks
-        futures::future::join_all(tasks)
-            .await
-            .into_iter()
-            .collect::<Result<Vec<_>, _>>()?;
+        supervisor.run().await
+    }
 
-        Ok(())
+    // Saves the current twin state, if snapshots are configured
+    pub async fn save_snapshot(&self) {
+        if let Some(store) = &self.snapshot_store {
+            let vehicle = self.state.lock().await.vehicle().clone();
+            if let Err(e) = store.save(&vehicle).await {
+                error!("Failed to save twin snapshot: {:?}", e);
+            }
+        }
     }
 }
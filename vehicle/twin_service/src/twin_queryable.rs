@@ -0,0 +1,185 @@
+use crate::supervisor::{Criticality, Supervisor};
+use crate::system_state::SystemStateMachine;
+use crate::vehicle_state::VehicleState;
+use log::{error, info, trace};
+use prost::Message;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use vehicle_msgs::partial_json::from_partial_value;
+use vehicle_msgs::vehicle_msgs::Vehicle;
+use zenoh::bytes::Encoding;
+use zenoh::query::Query;
+
+// Path after the prefix answered with the recent system state transitions
+const TRANSITIONS_PATH: &str = "system_state/transitions";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TwinFormat {
+    Protobuf,
+    Json,
+}
+
+impl TwinFormat {
+    fn from_parameter(value: Option<&str>) -> Result<Self, String> {
+        match value {
+            None | Some("protobuf") => Ok(TwinFormat::Protobuf),
+            Some("json") => Ok(TwinFormat::Json),
+            Some(other) => Err(format!(
+                "Unknown format '{}', expected protobuf or json",
+                other
+            )),
+        }
+    }
+}
+
+// Answers queries for the current digital twin state
+pub struct TwinQueryable {
+    state: Arc<Mutex<VehicleState>>,
+    key_expr: String,
+}
+
+impl TwinQueryable {
+    pub fn new(state: Arc<Mutex<VehicleState>>, key_expr: String) -> Self {
+        Self { state, key_expr }
+    }
+
+    pub fn run(&self, session: Arc<zenoh::Session>, supervisor: &mut Supervisor) {
+        let state = Arc::clone(&self.state);
+        let prefix = self.key_expr.clone();
+        supervisor.spawn("twin queryable", Criticality::Optional, move || {
+            serve(Arc::clone(&state), session.clone(), prefix.clone())
+        });
+    }
+}
+
+async fn serve(state: Arc<Mutex<VehicleState>>, session: Arc<zenoh::Session>, prefix: String) {
+    let queryable = match session.declare_queryable(format!("{}/**", prefix)).await {
+        Ok(queryable) => queryable,
+        Err(e) => {
+            error!("Failed to declare digital twin queryable: {:?}", e);
+            return;
+        }
+    };
+    info!("Answering digital twin queries on '{}/**'", prefix);
+
+    let transitions_key = format!("{}/{}", prefix, TRANSITIONS_PATH);
+    while let Ok(query) = queryable.recv_async().await {
+        trace!("Received digital twin query: {}", query.selector());
+        let result = if query.key_expr().as_str() == transitions_key {
+            let transitions = transitions(state.lock().await.system_state());
+            answer_transitions(&query, &transitions).await
+        } else {
+            let vehicle = state.lock().await.vehicle().clone();
+            answer(&query, &prefix, &vehicle).await
+        };
+        if let Err(e) = result {
+            error!("Failed to answer query {}: {:?}", query.selector(), e);
+        }
+    }
+}
+
+// Recent transitions of the low voltage system state, oldest first
+fn transitions(system_state: &SystemStateMachine) -> Value {
+    system_state
+        .transitions()
+        .map(|transition| {
+            json!({
+                "from": transition.from.to_string(),
+                "to": transition.to.to_string(),
+                "timestamp": transition.timestamp.to_rfc3339(),
+            })
+        })
+        .collect()
+}
+
+// There is no protobuf message for the transitions, they are always JSON
+async fn answer_transitions(
+    query: &Query,
+    transitions: &Value,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    query
+        .reply(query.key_expr().clone(), serde_json::to_vec(transitions)?)
+        .encoding(Encoding::APPLICATION_JSON)
+        .await?;
+    Ok(())
+}
+
+async fn answer(
+    query: &Query,
+    prefix: &str,
+    vehicle: &Vehicle,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let format = match TwinFormat::from_parameter(query.parameters().get("format")) {
+        Ok(format) => format,
+        Err(e) => {
+            query.reply_err(e).await?;
+            return Ok(());
+        }
+    };
+
+    // Wildcard queries get the whole vehicle, others the sub-tree of their path
+    let key_expr = query.key_expr();
+    let (reply_key, path) = if key_expr.is_wild() {
+        (prefix.to_string(), Vec::new())
+    } else {
+        let path = key_expr
+            .as_str()
+            .strip_prefix(prefix)
+            .unwrap_or_default()
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        (key_expr.to_string(), path)
+    };
+
+    let payload = match select(vehicle, &path, format) {
+        Ok(payload) => payload,
+        Err(e) => {
+            query.reply_err(e).await?;
+            return Ok(());
+        }
+    };
+    let encoding = match format {
+        TwinFormat::Protobuf => Encoding::APPLICATION_PROTOBUF,
+        TwinFormat::Json => Encoding::APPLICATION_JSON,
+    };
+    query.reply(reply_key, payload).encoding(encoding).await?;
+    Ok(())
+}
+
+// Encodes the value at `path` in the vehicle model. As protobuf can only carry
+// whole messages, sub-trees are sent as a Vehicle with just that sub-tree set
+pub fn select(vehicle: &Vehicle, path: &[&str], format: TwinFormat) -> Result<Vec<u8>, String> {
+    if path.is_empty() {
+        return match format {
+            TwinFormat::Protobuf => Ok(vehicle.encode_to_vec()),
+            TwinFormat::Json => serde_json::to_vec(vehicle).map_err(|e| e.to_string()),
+        };
+    }
+
+    let value = serde_json::to_value(vehicle).map_err(|e| e.to_string())?;
+    let pointer = format!("/{}", path.join("/"));
+    let sub_tree = match value.pointer(&pointer) {
+        Some(Value::Null) | None => {
+            return Err(format!(
+                "No value at '{}' in the digital twin",
+                path.join("/")
+            ))
+        }
+        Some(sub_tree) => sub_tree.clone(),
+    };
+
+    match format {
+        TwinFormat::Json => serde_json::to_vec(&sub_tree).map_err(|e| e.to_string()),
+        TwinFormat::Protobuf => {
+            let pruned = path.iter().rev().fold(sub_tree, |child, segment| {
+                let mut parent = Map::new();
+                parent.insert(segment.to_string(), child);
+                Value::Object(parent)
+            });
+            let pruned: Vehicle = from_partial_value(pruned).map_err(|e| e.to_string())?;
+            Ok(pruned.encode_to_vec())
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use crate::config::{TwinServiceConfig, TwinSyncConfig};
+use crate::supervisor::{Criticality, Supervisor};
+use crate::vehicle_state::VehicleState;
+use common::{DataPublisher, Topic, TopicPublisher, WireFormat};
+use log::{error, info, trace};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use vehicle_msgs::vehicle_msgs::Vehicle;
+use vehicle_msgs::vehicle_twin_sync::VehicleDelta;
+
+// Key expressions and timing of the twin sync, resolved from the config
+#[derive(Debug, Clone)]
+pub struct TwinSyncPublication {
+    pub snapshot_key_expr: String,
+    pub delta_key_expr: String,
+    pub interval: Duration,
+    pub format: WireFormat,
+}
+
+impl TwinSyncPublication {
+    pub fn from_config(sync: &TwinSyncConfig, config: &TwinServiceConfig) -> Result<Self, String> {
+        let key_expr = config.resolve_topic(&sync.key_expr)?;
+        Ok(Self {
+            snapshot_key_expr: format!("{}/snapshot", key_expr),
+            delta_key_expr: format!("{}/delta", key_expr),
+            interval: Duration::from_millis(sync.interval),
+            format: sync.format,
+        })
+    }
+}
+
+// Mirrors the whole Vehicle model to the cloud: a full snapshot whenever the
+// cloud (re)connects, then the fields that changed since the last sync.
+// Subscribers joining later get the snapshot the next delta applies to with a
+// get on the snapshot key expression
+pub struct TwinSync {
+    state: Arc<Mutex<VehicleState>>,
+    publication: TwinSyncPublication,
+    // Model as last sent to the cloud, None while nobody is subscribed
+    synced: Arc<Mutex<Option<Vehicle>>>,
+}
+
+impl TwinSync {
+    pub fn new(state: Arc<Mutex<VehicleState>>, publication: TwinSyncPublication) -> Self {
+        Self {
+            state,
+            publication,
+            synced: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn run(&self, session: Arc<zenoh::Session>, supervisor: &mut Supervisor) {
+        let state = Arc::clone(&self.state);
+        let synced = Arc::clone(&self.synced);
+        let publication = self.publication.clone();
+        let sync_session = session.clone();
+        supervisor.spawn("twin sync", Criticality::Optional, move || {
+            sync_twin(
+                Arc::clone(&state),
+                Arc::clone(&synced),
+                sync_session.clone(),
+                publication.clone(),
+            )
+        });
+
+        let state = Arc::clone(&self.state);
+        let synced = Arc::clone(&self.synced);
+        let publication = self.publication.clone();
+        supervisor.spawn("twin sync snapshot", Criticality::Optional, move || {
+            serve_snapshot(
+                Arc::clone(&state),
+                Arc::clone(&synced),
+                session.clone(),
+                publication.clone(),
+            )
+        });
+    }
+}
+
+async fn sync_twin(
+    state: Arc<Mutex<VehicleState>>,
+    synced_vehicle: Arc<Mutex<Option<Vehicle>>>,
+    session: Arc<zenoh::Session>,
+    publication: TwinSyncPublication,
+) {
+    let snapshot_topic =
+        Topic::<Vehicle>::from_key_expr(publication.snapshot_key_expr.clone(), publication.format);
+    let delta_topic = Topic::<VehicleDelta>::from_key_expr(
+        publication.delta_key_expr.clone(),
+        publication.format,
+    );
+    let publishers = tokio::try_join!(
+        TopicPublisher::new(session.clone(), snapshot_topic),
+        TopicPublisher::new(session, delta_topic)
+    );
+    let (snapshot_publisher, delta_publisher) = match publishers {
+        Ok(publishers) => publishers,
+        Err(e) => {
+            error!(
+                "Failed to create Zenoh publishers for the twin sync: {:?}",
+                e
+            );
+            return;
+        }
+    };
+    info!(
+        "Syncing the twin to '{}' and '{}' every {:?} ({:?})",
+        publication.snapshot_key_expr,
+        publication.delta_key_expr,
+        publication.interval,
+        publication.format
+    );
+
+    // JSON representation of the model as last sent to the cloud, None until
+    // a snapshot was sent to the currently connected subscribers
+    let mut synced: Option<Value> = None;
+    loop {
+        let subscribed =
+            snapshot_publisher.has_subscribers().await || delta_publisher.has_subscribers().await;
+        if !subscribed {
+            if synced.take().is_some() {
+                info!("Twin sync subscribers are gone, waiting for them to reconnect");
+                *synced_vehicle.lock().await = None;
+            }
+        } else {
+            let vehicle = state.lock().await.vehicle().clone();
+            match serde_json::to_value(&vehicle) {
+                Ok(current) => match &synced {
+                    None => match snapshot_publisher.publish(vehicle.clone()).await {
+                        Ok(_) => {
+                            info!("Published full twin snapshot");
+                            synced = Some(current);
+                            *synced_vehicle.lock().await = Some(vehicle);
+                        }
+                        Err(e) => error!("Failed to publish twin snapshot: {:?}", e),
+                    },
+                    Some(previous) => {
+                        let delta = VehicleDelta::diff(previous, &current);
+                        if delta.changes.is_empty() {
+                            trace!("Twin unchanged since the last sync");
+                        } else {
+                            trace!("Publishing {} twin changes", delta.changes.len());
+                            match delta_publisher.publish(delta).await {
+                                Ok(_) => {
+                                    synced = Some(current);
+                                    *synced_vehicle.lock().await = Some(vehicle);
+                                }
+                                Err(e) => error!("Failed to publish twin delta: {:?}", e),
+                            }
+                        }
+                    }
+                },
+                Err(e) => error!("Failed to convert the twin for syncing: {:?}", e),
+            }
+        }
+
+        tokio::time::sleep(publication.interval).await;
+    }
+}
+
+// Answers gets on the snapshot key expression with the model the next delta
+// applies to, or the current one if nothing was synced yet
+async fn serve_snapshot(
+    state: Arc<Mutex<VehicleState>>,
+    synced: Arc<Mutex<Option<Vehicle>>>,
+    session: Arc<zenoh::Session>,
+    publication: TwinSyncPublication,
+) {
+    let key_expr = publication.snapshot_key_expr;
+    let queryable = match session.declare_queryable(key_expr.clone()).await {
+        Ok(queryable) => queryable,
+        Err(e) => {
+            error!("Failed to declare twin snapshot queryable: {:?}", e);
+            return;
+        }
+    };
+    info!("Answering twin snapshot queries on '{}'", key_expr);
+
+    let topic = Topic::<Vehicle>::from_key_expr(key_expr.clone(), publication.format);
+    while let Ok(query) = queryable.recv_async().await {
+        trace!("Received twin snapshot query: {}", query.selector());
+        let synced = synced.lock().await.clone();
+        let vehicle = match synced {
+            Some(vehicle) => vehicle,
+            None => state.lock().await.vehicle().clone(),
+        };
+        let result = match topic.encode(&vehicle) {
+            Ok(payload) => {
+                query
+                    .reply(key_expr.clone(), payload)
+                    .encoding(topic.format().encoding())
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("Failed to answer twin snapshot query: {:?}", e);
+        }
+    }
+}
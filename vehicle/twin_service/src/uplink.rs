@@ -0,0 +1,221 @@
+use crate::config::{BatchCompression, BatchingConfig, TwinServiceConfig};
+use crate::telemetry_buffer::TelemetryBuffer;
+use common::{EncodedMessage, Topic, TopicMessage, TopicPublisher, WireFormat};
+use log::{error, info, trace, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use vehicle_msgs::vehicle_telemetry_batch::{
+    Compression, TelemetryBatch, TelemetryBatchFrame, TelemetryRecord,
+};
+
+// Events waiting for the batcher before the event publishers are slowed down
+const BATCH_CHANNEL_SIZE: usize = 100;
+
+impl From<BatchCompression> for Compression {
+    fn from(compression: BatchCompression) -> Self {
+        match compression {
+            BatchCompression::None => Compression::None,
+            BatchCompression::Zstd => Compression::Zstd,
+            BatchCompression::Lz4 => Compression::Lz4,
+        }
+    }
+}
+
+// Batching of the cloud events, with the key expression resolved
+#[derive(Debug, Clone)]
+pub struct BatchPublication {
+    pub key_expr: String,
+    pub window: Duration,
+    pub max_events: usize,
+    pub compression: Compression,
+}
+
+impl BatchPublication {
+    pub fn from_config(
+        batching: &BatchingConfig,
+        config: &TwinServiceConfig,
+    ) -> Result<Self, String> {
+        if batching.window == 0 || batching.max_events == 0 {
+            return Err("Batching window and max_events must be greater than 0".to_string());
+        }
+        Ok(Self {
+            key_expr: config.resolve_topic(&batching.topic)?,
+            window: Duration::from_millis(batching.window),
+            max_events: batching.max_events,
+            compression: batching.compression.into(),
+        })
+    }
+}
+
+// An encoded cloud event on its way to the batcher
+#[derive(Debug)]
+pub struct BatchRecord {
+    pub priority: u8,
+    pub message: EncodedMessage,
+}
+
+// How cloud events leave the vehicle: put on their own key expression, or
+// handed to the batcher, and buffered on disk while the cloud is unreachable
+#[derive(Debug, Clone, Default)]
+pub struct Uplink {
+    buffer: Option<Arc<TelemetryBuffer>>,
+    batch_tx: Option<mpsc::Sender<BatchRecord>>,
+}
+
+impl Uplink {
+    pub fn new(
+        buffer: Option<Arc<TelemetryBuffer>>,
+        batch_tx: Option<mpsc::Sender<BatchRecord>>,
+    ) -> Self {
+        Self { buffer, batch_tx }
+    }
+
+    // Creates the channel events are batched through when batching is enabled
+    pub fn batch_channel() -> (mpsc::Sender<BatchRecord>, mpsc::Receiver<BatchRecord>) {
+        mpsc::channel(BATCH_CHANNEL_SIZE)
+    }
+
+    pub async fn send<T: TopicMessage>(
+        &self,
+        publisher: &TopicPublisher<T>,
+        priority: u8,
+        event: T,
+        stale_signals: Vec<String>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let message = publisher.encode(&event, stale_signals)?;
+        match &self.batch_tx {
+            Some(batch_tx) => batch_tx
+                .send(BatchRecord { priority, message })
+                .await
+                .map_err(|_| "Telemetry batcher stopped".into()),
+            None => publish_or_buffer(publisher, self.buffer.as_deref(), priority, &message).await,
+        }
+    }
+}
+
+// Publishes an event, or stores it in the buffer while nobody is subscribed
+// to it or earlier events of the same kind are still waiting to be replayed
+async fn publish_or_buffer<T: TopicMessage>(
+    publisher: &TopicPublisher<T>,
+    buffer: Option<&TelemetryBuffer>,
+    priority: u8,
+    message: &EncodedMessage,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(buffer) = buffer else {
+        return publisher.put(message).await;
+    };
+
+    let key_expr = publisher.topic().key_expr();
+    if !buffer.has_pending(key_expr) && publisher.has_subscribers().await {
+        match publisher.put(message).await {
+            Ok(_) => return Ok(()),
+            Err(e) => warn!("Failed to publish to '{}', buffering: {:?}", key_expr, e),
+        }
+    }
+    trace!("Buffering event for '{}'", key_expr);
+    buffer.push(priority, message).await?;
+    Ok(())
+}
+
+// Collects the events sent through the uplink and publishes them as one
+// compressed batch once the window is over or `max_events` are collected
+pub async fn publish_batches(
+    session: Arc<zenoh::Session>,
+    batching: BatchPublication,
+    vehicle_id: String,
+    buffer: Option<Arc<TelemetryBuffer>>,
+    mut batch_rx: mpsc::Receiver<BatchRecord>,
+) {
+    let topic = Topic::<TelemetryBatchFrame>::from_key_expr(
+        batching.key_expr.clone(),
+        WireFormat::Protobuf,
+    );
+    let publisher = match TopicPublisher::new(session, topic).await {
+        Ok(publisher) => publisher,
+        Err(e) => {
+            error!("Failed to create Zenoh publisher for batches: {:?}", e);
+            return;
+        }
+    };
+    info!(
+        "Publishing event batches to '{}' every {:?} or {} events ({:?})",
+        batching.key_expr, batching.window, batching.max_events, batching.compression
+    );
+
+    let mut records = Vec::new();
+    let mut priority = 0;
+    let mut window_end = None;
+    loop {
+        let received = match window_end {
+            Some(window_end) => tokio::time::timeout_at(window_end, batch_rx.recv())
+                .await
+                .ok(),
+            None => Some(batch_rx.recv().await),
+        };
+        let closed = match received {
+            Some(Some(record)) => {
+                if records.is_empty() {
+                    window_end = Some(Instant::now() + batching.window);
+                }
+                priority = priority.max(record.priority);
+                records.push(to_record(record.message));
+                if records.len() < batching.max_events {
+                    continue;
+                }
+                false
+            }
+            Some(None) => true,
+            // The window is over
+            None => false,
+        };
+
+        if !records.is_empty() {
+            let batch = TelemetryBatch {
+                vehicle_id: vehicle_id.clone(),
+                records: std::mem::take(&mut records),
+            };
+            let count = batch.records.len();
+            let result = publish_batch(
+                &publisher,
+                buffer.as_deref(),
+                priority,
+                &batch,
+                batching.compression,
+            )
+            .await;
+            match result {
+                Ok(_) => trace!("Published batch of {} events", count),
+                Err(e) => error!("Failed to publish batch of {} events: {:?}", count, e),
+            }
+        }
+        priority = 0;
+        window_end = None;
+        if closed {
+            return;
+        }
+    }
+}
+
+async fn publish_batch(
+    publisher: &TopicPublisher<TelemetryBatchFrame>,
+    buffer: Option<&TelemetryBuffer>,
+    priority: u8,
+    batch: &TelemetryBatch,
+    compression: Compression,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let frame = TelemetryBatchFrame::compress(batch, compression)?;
+    let message = publisher.encode(&frame, Vec::new())?;
+    publish_or_buffer(publisher, buffer, priority, &message).await
+}
+
+// Keeps the envelope of the event as JSON, as it would be in the attachment
+fn to_record(message: EncodedMessage) -> TelemetryRecord {
+    TelemetryRecord {
+        attachment: serde_json::to_vec(&message.envelope).unwrap_or_default(),
+        key_expr: message.key_expr,
+        encoding: message.encoding,
+        payload: message.payload,
+    }
+}
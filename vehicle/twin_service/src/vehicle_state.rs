@@ -1,11 +1,24 @@
-use std::fmt;
+use crate::command_rules::CommandRules;
+use crate::freshness::SignalFreshness;
+use crate::system_state::SystemStateMachine;
+use std::str::FromStr;
 
-use log::error;
+pub use crate::system_state::LowVoltageSystemState;
+
+use common::topics::{
+    BATTERY_STATE_TOPIC, CURRENT_LOCATION_TOPIC, EXTERIOR_TOPIC, HORN_TOPIC, LIGHTS_TOPIC,
+    LOCK_STATE_TOPIC, POWERTRAIN_TOPIC, SPEED_TOPIC, TIRES_TOPIC, TRIP_DATA_TOPIC,
+};
+use log::{error, warn};
+use serde_json::{json, Value};
 use vehicle_msgs::battery::BatteryData;
 use vehicle_msgs::current_location::CurrentLocation;
 use vehicle_msgs::exterior::Exterior;
+use vehicle_msgs::horn::Horn;
+use vehicle_msgs::lights::Lights;
+use vehicle_msgs::powertrain::PowertrainState;
 use vehicle_msgs::speed::Speed;
-use vehicle_msgs::state::LockState;
+use vehicle_msgs::state::{LockState, State};
 use vehicle_msgs::tires::Tires;
 use vehicle_msgs::trip_data::TripData;
 use vehicle_msgs::vehicle_cloud_events::TirePressure;
@@ -13,17 +26,20 @@ use vehicle_msgs::vehicle_cloud_events::{
     BatteryEvent, CurrentLocationEvent, ExteriorEvent, SpeedEvent, SystemStateEvent,
     TirePressureEvent, TripDataEvent,
 };
-use vehicle_msgs::vehicle_msgs::{
-    Vehicle, VehicleChassisAxleRow1WheelLeftTire, VehicleChassisAxleRow2WheelLeftTire,
-    VehicleCurrentLocation, VehicleExterior,
-};
+use vehicle_msgs::vehicle_msgs::{Vehicle, VehicleCurrentLocation, VehicleExterior};
+use vehicle_msgs::vss_path::VssPath;
 
 pub trait VehicleMessage {
+    // Intra-vehicle topic the message is received on
+    const SIGNAL: &'static str;
+
     fn update_state(self, state: &mut Vehicle);
 }
 
 // VehicleMessage implementations
 impl VehicleMessage for BatteryData {
+    const SIGNAL: &'static str = BATTERY_STATE_TOPIC;
+
     fn update_state(self, state: &mut Vehicle) {
         if let Some(powertrain) = &mut state.powertrain {
             if let Some(traction_battery) = &mut powertrain.traction_battery {
@@ -65,17 +81,22 @@ impl VehicleMessage for BatteryData {
 }
 
 impl VehicleMessage for CurrentLocation {
+    const SIGNAL: &'static str = CURRENT_LOCATION_TOPIC;
+
     fn update_state(self, state: &mut Vehicle) {
         state.current_location = Some(VehicleCurrentLocation {
             latitude: self.latitude,
             longitude: self.longitude,
             altitude: self.altitude,
+            heading: self.heading,
             ..state.current_location.clone().unwrap_or_default()
         });
     }
 }
 
 impl VehicleMessage for Exterior {
+    const SIGNAL: &'static str = EXTERIOR_TOPIC;
+
     fn update_state(self, state: &mut Vehicle) {
         state.exterior = Some(VehicleExterior {
             air_temperature: self.air_temperature,
@@ -86,81 +107,43 @@ impl VehicleMessage for Exterior {
 }
 
 impl VehicleMessage for Speed {
+    const SIGNAL: &'static str = SPEED_TOPIC;
+
     fn update_state(self, state: &mut Vehicle) {
         state.speed = self.value;
     }
 }
 
 impl VehicleMessage for Tires {
-    fn update_state(self, state: &mut Vehicle) {
-        // Ensure row1 and row2 exist
-        if let Some(chassis) = &mut state.chassis {
-            if let Some(axle) = &mut chassis.axle {
-                // Handle front tire (row1)
-                if let Some(row1) = &mut axle.row1 {
-                    if let Some(wheel) = &mut row1.wheel {
-                        if let Some(left_wheel) = &mut wheel.left {
-                            if let Some(tire) = &mut left_wheel.tire {
-                                if let Some(front_tire) = &self.front_tire {
-                                    tire.pressure = front_tire.pressure;
-                                    tire.temperature = front_tire.temperature;
-                                    tire.is_pressure_low = front_tire.is_pressure_low;
-                                }
-                            } else {
-                                // Initialize tire if it's None
-                                left_wheel.tire = Some(VehicleChassisAxleRow1WheelLeftTire {
-                                    pressure: self.front_tire.unwrap_or_default().pressure,
-                                    temperature: self.front_tire.unwrap_or_default().temperature,
-                                    is_pressure_low: self
-                                        .front_tire
-                                        .unwrap_or_default()
-                                        .is_pressure_low,
-                                });
-                            }
-                        }
-                    }
-                }
+    const SIGNAL: &'static str = TIRES_TOPIC;
 
-                // Handle rear tire (row2)
-                if let Some(row2) = &mut axle.row2 {
-                    if let Some(wheel) = &mut row2.wheel {
-                        if let Some(left_wheel) = &mut wheel.left {
-                            if let Some(tire) = &mut left_wheel.tire {
-                                if let Some(rear_tire) = &self.rear_tire {
-                                    tire.pressure = rear_tire.pressure;
-                                    tire.temperature = rear_tire.temperature;
-                                    tire.is_pressure_low = rear_tire.is_pressure_low;
-                                }
-                            } else {
-                                // Initialize tire if it's None
-                                left_wheel.tire = Some(VehicleChassisAxleRow2WheelLeftTire {
-                                    pressure: self.rear_tire.unwrap_or_default().pressure,
-                                    temperature: self.rear_tire.unwrap_or_default().temperature,
-                                    is_pressure_low: self
-                                        .rear_tire
-                                        .unwrap_or_default()
-                                        .is_pressure_low,
-                                });
-                            }
-                        } else {
-                            error!("Wheel is not initialized");
-                        }
-                    } else {
-                        error!("Wheel is not initialized");
-                    }
-                } else {
-                    error!("Row2 is not initialized");
+    fn update_state(self, state: &mut Vehicle) {
+        let mut values = Vec::new();
+        for (row, tire) in [("Row1", self.front_tire), ("Row2", self.rear_tire)] {
+            let Some(tire) = tire else {
+                continue;
+            };
+            for (field, value) in [
+                ("Pressure", json!(tire.pressure)),
+                ("Temperature", json!(tire.temperature)),
+                ("IsPressureLow", json!(tire.is_pressure_low)),
+            ] {
+                let path = format!("Vehicle.Chassis.Axle.{}.Wheel.Left.Tire.{}", row, field);
+                match path.parse::<VssPath>() {
+                    Ok(path) => values.push((path, value)),
+                    Err(e) => error!("{}", e),
                 }
-            } else {
-                error!("Axle is not initialized");
             }
-        } else {
-            error!("Chassis is not initialized");
+        }
+        if let Err(e) = state.set_paths(values) {
+            error!("Failed to update tires: {}", e);
         }
     }
 }
 
 impl VehicleMessage for TripData {
+    const SIGNAL: &'static str = TRIP_DATA_TOPIC;
+
     fn update_state(self, state: &mut Vehicle) {
         state.start_time = self.start_time;
         state.traveled_distance = self.traveled_distance;
@@ -171,73 +154,185 @@ impl VehicleMessage for TripData {
     }
 }
 
-impl VehicleMessage for LockState {
+impl VehicleMessage for Lights {
+    const SIGNAL: &'static str = LIGHTS_TOPIC;
+
     fn update_state(self, state: &mut Vehicle) {
-        let system_state_str = match self.state {
-            0 => "UNDEFINED",
-            1 => "LOCK",
-            2 => "OFF",
-            3 => "ACC",
-            4 => "ON",
-            5 => "START",
-            _ => "UNDEFINED",
-        };
+        let lights = state
+            .body
+            .get_or_insert_with(Default::default)
+            .lights
+            .get_or_insert_with(Default::default);
+        let beam = lights.beam.get_or_insert_with(Default::default);
+        beam.low.get_or_insert_with(Default::default).is_on = self.is_low_beam_on;
+        beam.high.get_or_insert_with(Default::default).is_on = self.is_high_beam_on;
+        lights.parking.get_or_insert_with(Default::default).is_on = self.is_parking_on;
+        lights.running.get_or_insert_with(Default::default).is_on = self.is_running_on;
+    }
+}
 
-        state.low_voltage_system_state = system_state_str.to_string();
+impl VehicleMessage for Horn {
+    const SIGNAL: &'static str = HORN_TOPIC;
+
+    fn update_state(self, state: &mut Vehicle) {
+        state
+            .body
+            .get_or_insert_with(Default::default)
+            .horn
+            .get_or_insert_with(Default::default)
+            .is_active = self.is_active;
     }
 }
 
-pub enum LowVoltageSystemState {
-    UNDEFINED,
-    LOCK,
-    OFF,
-    ACC,
-    ON,
-    START,
+// Messages reporting the low voltage system state, applied through the
+// system state machine instead of being written to the model directly
+pub trait SystemStateMessage {
+    // Intra-vehicle topic the message is received on
+    const SIGNAL: &'static str;
+
+    // State the message moves the vehicle to from `current`, None if it
+    // does not change it
+    fn system_state(&self, current: LowVoltageSystemState) -> Option<LowVoltageSystemState>;
 }
 
-impl fmt::Display for LowVoltageSystemState {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s = match self {
-            LowVoltageSystemState::UNDEFINED => "UNDEFINED",
-            LowVoltageSystemState::LOCK => "LOCK",
-            LowVoltageSystemState::OFF => "OFF",
-            LowVoltageSystemState::ACC => "ACC",
-            LowVoltageSystemState::ON => "ON",
-            LowVoltageSystemState::START => "START",
-        };
-        write!(f, "{}", s)
+impl SystemStateMessage for LockState {
+    const SIGNAL: &'static str = LOCK_STATE_TOPIC;
+
+    fn system_state(&self, _current: LowVoltageSystemState) -> Option<LowVoltageSystemState> {
+        Some(
+            State::try_from(self.state)
+                .map(LowVoltageSystemState::from)
+                .unwrap_or(LowVoltageSystemState::UNDEFINED),
+        )
+    }
+}
+
+impl SystemStateMessage for PowertrainState {
+    const SIGNAL: &'static str = POWERTRAIN_TOPIC;
+
+    fn system_state(&self, current: LowVoltageSystemState) -> Option<LowVoltageSystemState> {
+        // A running engine means the vehicle is started, stopping it leaves
+        // the ignition on. A stopped engine says nothing about other states
+        if self.is_engine_on {
+            Some(LowVoltageSystemState::START)
+        } else if current == LowVoltageSystemState::START {
+            Some(LowVoltageSystemState::ON)
+        } else {
+            None
+        }
     }
 }
 
 // Vehicle State
 #[derive(Debug, Default)]
 pub struct VehicleState {
-    pub vehicle: Vehicle,
+    vehicle: Vehicle,
     pub vehicle_id: String,
+    pub command_rules: CommandRules,
+    system_state: SystemStateMachine,
+    pub freshness: SignalFreshness,
 }
 
 impl VehicleState {
+    pub fn new(
+        vehicle: Vehicle,
+        vehicle_id: String,
+        command_rules: CommandRules,
+        freshness: SignalFreshness,
+    ) -> Self {
+        let initial_state = vehicle
+            .low_voltage_system_state
+            .parse()
+            .unwrap_or_else(|e| {
+                warn!("{}, starting from UNDEFINED", e);
+                LowVoltageSystemState::UNDEFINED
+            });
+        Self {
+            vehicle,
+            vehicle_id,
+            command_rules,
+            system_state: SystemStateMachine::new(initial_state),
+            freshness,
+        }
+    }
+
+    pub fn vehicle(&self) -> &Vehicle {
+        &self.vehicle
+    }
+
+    pub fn system_state(&self) -> &SystemStateMachine {
+        &self.system_state
+    }
+
+    // Sets fields of the vehicle model, see `Vehicle::set_paths`
+    pub fn set_paths(
+        &mut self,
+        values: impl IntoIterator<Item = (VssPath, Value)>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.vehicle.set_paths(values)
+    }
+
     pub async fn update<C: VehicleMessage + Send + 'static>(&mut self, component: C) {
         component.update_state(&mut self.vehicle);
+        self.freshness.updated(C::SIGNAL);
+    }
+
+    pub async fn update_system_state<C: SystemStateMessage + Send + 'static>(
+        &mut self,
+        message: C,
+    ) {
+        self.freshness.updated(C::SIGNAL);
+        let Some(new_state) = message.system_state(self.system_state.current()) else {
+            return;
+        };
+        if let Err(e) = self.change_state(new_state) {
+            warn!("Ignoring system state update: {}", e);
+        }
     }
 
-    pub fn change_state(&mut self, new_state: LowVoltageSystemState) {
-        // TODO: Implement the logic to verify if the state can be changed?
+    // The only place the low voltage system state of the model is changed
+    pub fn change_state(&mut self, new_state: LowVoltageSystemState) -> Result<(), String> {
+        self.system_state.transition(new_state)?;
         self.vehicle.low_voltage_system_state = new_state.to_string();
+        Ok(())
+    }
+
+    // Checks the command rules and the system state transition the command
+    // leads to, returning the rejection reason if the command must not be
+    // forwarded to the vehicle
+    pub fn validate_command(&self, command: &VehicleCommand) -> Result<(), String> {
+        if let Some(target) = command.target_system_state() {
+            self.system_state.check_transition(target)?;
+        }
+        self.command_rules.check(command, &self.vehicle)
     }
 
-    pub fn is_valid_command(&self, command: &VehicleCommand) -> bool {
-        // TODO: for now always return true
+    // Starts the cooldown of a command once it was forwarded to the vehicle
+    pub fn command_forwarded(&mut self, command: &VehicleCommand) {
+        self.command_rules.forwarded(command);
+    }
+
+    // Whether the vehicle reports the state a command asked for
+    pub fn is_command_applied(&self, command: &VehicleCommand) -> bool {
+        if let Some(target) = command.target_system_state() {
+            return self.system_state.current() == target;
+        }
         match command {
-            VehicleCommand::Lock => true,
-            VehicleCommand::Unlock => true,
-            VehicleCommand::LightOn => true,
-            VehicleCommand::LightOff => true,
-            VehicleCommand::HornOn => true,
-            VehicleCommand::HornOff => true,
-            VehicleCommand::EngineOn => true,
-            VehicleCommand::EngineOff => true,
+            VehicleCommand::LightOn => self.to_lights().is_low_beam_on,
+            VehicleCommand::LightOff => !self.to_lights().is_low_beam_on,
+            VehicleCommand::HornOn | VehicleCommand::HornOff => {
+                let is_active = self
+                    .vehicle
+                    .body
+                    .as_ref()
+                    .and_then(|body| body.horn.as_ref())
+                    .is_some_and(|horn| horn.is_active);
+                is_active == (*command == VehicleCommand::HornOn)
+            }
+            VehicleCommand::Lock
+            | VehicleCommand::Unlock
+            | VehicleCommand::EngineOn
+            | VehicleCommand::EngineOff => false,
         }
     }
 
@@ -245,6 +340,22 @@ impl VehicleState {
         self.vehicle_id.clone()
     }
 
+    pub fn to_lights(&self) -> Lights {
+        let lights = self
+            .vehicle
+            .body
+            .as_ref()
+            .and_then(|body| body.lights.clone())
+            .unwrap_or_default();
+        let beam = lights.beam.unwrap_or_default();
+        Lights {
+            is_low_beam_on: beam.low.unwrap_or_default().is_on,
+            is_high_beam_on: beam.high.unwrap_or_default().is_on,
+            is_parking_on: lights.parking.unwrap_or_default().is_on,
+            is_running_on: lights.running.unwrap_or_default().is_on,
+        }
+    }
+
     pub fn to_battery_event(&self) -> Option<BatteryEvent> {
         if let Some(powertrain) = &self.vehicle.powertrain {
             if let Some(traction_battery) = &powertrain.traction_battery {
@@ -363,7 +474,7 @@ impl VehicleState {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum VehicleCommand {
     Lock,
     Unlock,
@@ -374,3 +485,38 @@ pub enum VehicleCommand {
     EngineOn,
     EngineOff,
 }
+
+impl VehicleCommand {
+    // System state the vehicle ends up in once the command is applied
+    pub fn target_system_state(&self) -> Option<LowVoltageSystemState> {
+        match self {
+            VehicleCommand::Lock => Some(LowVoltageSystemState::LOCK),
+            VehicleCommand::Unlock => Some(LowVoltageSystemState::ON),
+            VehicleCommand::EngineOn => Some(LowVoltageSystemState::START),
+            VehicleCommand::EngineOff => Some(LowVoltageSystemState::ON),
+            _ => None,
+        }
+    }
+
+    pub const ALL: [VehicleCommand; 8] = [
+        VehicleCommand::Lock,
+        VehicleCommand::Unlock,
+        VehicleCommand::HornOn,
+        VehicleCommand::HornOff,
+        VehicleCommand::LightOn,
+        VehicleCommand::LightOff,
+        VehicleCommand::EngineOn,
+        VehicleCommand::EngineOff,
+    ];
+}
+
+impl FromStr for VehicleCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        VehicleCommand::ALL
+            .into_iter()
+            .find(|command| format!("{:?}", command) == s)
+            .ok_or_else(|| format!("Unknown vehicle command '{}'", s))
+    }
+}
@@ -1,14 +1,17 @@
+use crate::supervisor::{Criticality, Supervisor};
 use crate::vehicle_state::VehicleState;
 use common::topics::*;
-use common::SubscriberTaskSpawner;
+use common::{SubscriberTaskSpawner, Topic, TopicMessage};
 use log::trace;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
-use tokio::task::JoinHandle;
 use vehicle_msgs::battery::BatteryData;
 use vehicle_msgs::current_location::CurrentLocation;
 use vehicle_msgs::exterior::Exterior;
+use vehicle_msgs::horn::Horn;
+use vehicle_msgs::lights::Lights;
+use vehicle_msgs::powertrain::PowertrainState;
 use vehicle_msgs::speed::Speed;
 use vehicle_msgs::state::LockState;
 use vehicle_msgs::tires::Tires;
@@ -23,48 +26,46 @@ impl VehicleStateProvider {
         Self { state }
     }
 
-    pub async fn run(
-        &self,
-        session: Arc<zenoh::Session>,
-    ) -> Result<Vec<JoinHandle<()>>, Box<dyn std::error::Error + Send + Sync>> {
+    pub fn run(&self, session: Arc<zenoh::Session>, supervisor: &mut Supervisor) {
         let (lock_tx, mut lock_rx) = mpsc::channel::<LockState>(32);
-        let lock_task =
-            SubscriberTaskSpawner::spawn_task(session.clone(), LOCK_STATE_TOPIC, lock_tx);
+        subscribe(supervisor, &session, LOCK_STATE, lock_tx);
 
         let (battery_tx, mut battery_rx) = mpsc::channel::<BatteryData>(100);
-        let battery_task =
-            SubscriberTaskSpawner::spawn_task(session.clone(), BATTERY_STATE_TOPIC, battery_tx);
+        subscribe(supervisor, &session, BATTERY_STATE, battery_tx);
 
         let (exterior_tx, mut exterior_rx) = mpsc::channel::<Exterior>(100);
-        let exterior_task =
-            SubscriberTaskSpawner::spawn_task(session.clone(), EXTERIOR_TOPIC, exterior_tx);
+        subscribe(supervisor, &session, EXTERIOR, exterior_tx);
 
         let (speed_tx, mut speed_rx) = mpsc::channel::<Speed>(100);
-        let speed_task = SubscriberTaskSpawner::spawn_task(session.clone(), SPEED_TOPIC, speed_tx);
+        subscribe(supervisor, &session, SPEED, speed_tx);
 
         let (trip_data_tx, mut trip_data_rx) = mpsc::channel::<TripData>(100);
-        let trip_data_task =
-            SubscriberTaskSpawner::spawn_task(session.clone(), TRIP_DATA_TOPIC, trip_data_tx);
+        subscribe(supervisor, &session, TRIP_DATA, trip_data_tx);
 
         let (tires_tx, mut tires_rx) = mpsc::channel::<Tires>(100);
-        let tires_task = SubscriberTaskSpawner::spawn_task(session.clone(), TIRES_TOPIC, tires_tx);
+        subscribe(supervisor, &session, TIRES, tires_tx);
 
         let (current_location_tx, mut current_location_rx) = mpsc::channel::<CurrentLocation>(100);
-        let current_location_task = SubscriberTaskSpawner::spawn_task(
-            session.clone(),
-            CURRENT_LOCATION_TOPIC,
-            current_location_tx,
-        );
+        subscribe(supervisor, &session, CURRENT_LOCATION, current_location_tx);
+
+        let (lights_tx, mut lights_rx) = mpsc::channel::<Lights>(32);
+        subscribe(supervisor, &session, LIGHTS, lights_tx);
+
+        let (horn_tx, mut horn_rx) = mpsc::channel::<Horn>(32);
+        subscribe(supervisor, &session, HORN, horn_tx);
+
+        let (powertrain_tx, mut powertrain_rx) = mpsc::channel::<PowertrainState>(32);
+        subscribe(supervisor, &session, POWERTRAIN, powertrain_tx);
 
         let state = Arc::clone(&self.state);
 
-        let consumer_task = tokio::spawn(async move {
+        supervisor.spawn_once("vehicle state consumer", async move {
             loop {
                 tokio::select! {
                     Some(lock_state) = lock_rx.recv() => {
                         trace!("Received LockState: {:?}", lock_state);
                         let mut state = state.lock().await;
-                        state.update(lock_state).await;
+                        state.update_system_state(lock_state).await;
                     }
                     Some(exterior) = exterior_rx.recv() => {
                         trace!("Received Exterior: {:?}", exterior);
@@ -96,6 +97,21 @@ impl VehicleStateProvider {
                         let mut state = state.lock().await;
                         state.update(current_location).await;
                     }
+                    Some(lights) = lights_rx.recv() => {
+                        trace!("Received Lights: {:?}", lights);
+                        let mut state = state.lock().await;
+                        state.update(lights).await;
+                    }
+                    Some(horn) = horn_rx.recv() => {
+                        trace!("Received Horn: {:?}", horn);
+                        let mut state = state.lock().await;
+                        state.update(horn).await;
+                    }
+                    Some(powertrain) = powertrain_rx.recv() => {
+                        trace!("Received PowertrainState: {:?}", powertrain);
+                        let mut state = state.lock().await;
+                        state.update_system_state(powertrain).await;
+                    }
                     else => {
                         trace!("All channels closed.");
                         break;
@@ -103,16 +119,22 @@ impl VehicleStateProvider {
                 }
             }
         });
-
-        Ok(vec![
-            lock_task,
-            battery_task,
-            exterior_task,
-            speed_task,
-            trip_data_task,
-            current_location_task,
-            tires_task,
-            consumer_task,
-        ])
     }
 }
+
+// Supervised task forwarding the messages received on `topic` to the consumer
+fn subscribe<T>(
+    supervisor: &mut Supervisor,
+    session: &Arc<zenoh::Session>,
+    topic: Topic<T>,
+    sender: mpsc::Sender<T>,
+) where
+    T: TopicMessage,
+{
+    let session = Arc::clone(session);
+    supervisor.spawn(
+        format!("{} subscriber", topic.key_expr()),
+        Criticality::Critical,
+        move || SubscriberTaskSpawner::forward(session.clone(), topic.clone(), sender.clone()),
+    );
+}
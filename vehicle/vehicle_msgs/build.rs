@@ -11,6 +11,10 @@ fn main() -> Result<()> {
             // Cloud events
             "../../vehicle-cloud-api/proto/vehicle_cloud_events.proto",
             "../../vehicle-cloud-api/proto/vehicle_commands.proto",
+            "../../proto/command_status.proto",
+            "../../proto/telemetry_batch.proto",
+            "../../proto/twin_sync.proto",
+            "../../proto/recording.proto",
             // Intra-vehicle events
             "../../proto/lock_state.proto",
             "../../proto/speed.proto",
@@ -19,6 +23,9 @@ fn main() -> Result<()> {
             "../../proto/battery.proto",
             "../../proto/exterior.proto",
             "../../proto/current_location.proto",
+            "../../proto/lights.proto",
+            "../../proto/horn.proto",
+            "../../proto/powertrain.proto",
         ],
         &["../../vehicle-cloud-api/proto", "../../proto/"],
     )?;
@@ -0,0 +1,125 @@
+use crate::vehicle_telemetry_batch::{Compression, TelemetryBatch, TelemetryBatchFrame};
+use prost::Message;
+use std::io::Read;
+
+// zstd level used for batches, a good trade-off between speed and ratio
+const ZSTD_LEVEL: i32 = 3;
+
+// Largest decompressed batch accepted, so that a small malicious payload
+// can't exhaust the memory of the decoder
+pub const MAX_BATCH_SIZE: usize = 16 * 1024 * 1024; // in bytes
+
+impl TelemetryBatchFrame {
+    // Encodes and compresses a batch
+    pub fn compress(
+        batch: &TelemetryBatch,
+        compression: Compression,
+    ) -> Result<TelemetryBatchFrame, Box<dyn std::error::Error + Send + Sync>> {
+        let encoded = batch.encode_to_vec();
+        let data = match compression {
+            Compression::None => encoded,
+            Compression::Zstd => zstd::encode_all(encoded.as_slice(), ZSTD_LEVEL)?,
+            Compression::Lz4 => lz4_flex::compress_prepend_size(&encoded),
+        };
+        Ok(TelemetryBatchFrame {
+            compression: compression as i32,
+            data,
+        })
+    }
+
+    // Decompresses and decodes the batch of the frame
+    pub fn decompress(&self) -> Result<TelemetryBatch, Box<dyn std::error::Error + Send + Sync>> {
+        let compression = Compression::try_from(self.compression)
+            .map_err(|_| format!("Unknown batch compression {}", self.compression))?;
+        let encoded = match compression {
+            Compression::None => self.data.clone(),
+            Compression::Zstd => {
+                let mut encoded = Vec::new();
+                zstd::stream::read::Decoder::new(self.data.as_slice())?
+                    .take(MAX_BATCH_SIZE as u64 + 1)
+                    .read_to_end(&mut encoded)?;
+                if encoded.len() > MAX_BATCH_SIZE {
+                    return Err(too_large());
+                }
+                encoded
+            }
+            Compression::Lz4 => {
+                // The size is checked before the decompressor allocates it
+                let (size, _) = lz4_flex::block::uncompressed_size(&self.data)?;
+                if size > MAX_BATCH_SIZE {
+                    return Err(too_large());
+                }
+                lz4_flex::decompress_size_prepended(&self.data)?
+            }
+        };
+        Ok(TelemetryBatch::decode(encoded.as_slice())?)
+    }
+}
+
+fn too_large() -> Box<dyn std::error::Error + Send + Sync> {
+    format!("Batch exceeds {} bytes when decompressed", MAX_BATCH_SIZE).into()
+}
+
+// Decodes a payload received on the batch key expression
+pub fn decode_batch(
+    payload: &[u8],
+) -> Result<TelemetryBatch, Box<dyn std::error::Error + Send + Sync>> {
+    TelemetryBatchFrame::decode(payload)?.decompress()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vehicle_telemetry_batch::TelemetryRecord;
+
+    fn batch() -> TelemetryBatch {
+        TelemetryBatch {
+            vehicle_id: "VEHICLE1VIN".to_string(),
+            records: (0..50)
+                .map(|i| TelemetryRecord {
+                    key_expr: "cloud/telemetry/speed".to_string(),
+                    encoding: "application/protobuf".to_string(),
+                    payload: vec![i; 64],
+                    attachment: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn round_trips_every_compression() {
+        let batch = batch();
+        for compression in [Compression::None, Compression::Zstd, Compression::Lz4] {
+            let frame = TelemetryBatchFrame::compress(&batch, compression).unwrap();
+            assert_eq!(frame.compression, compression as i32);
+            assert_eq!(frame.decompress().unwrap(), batch);
+            assert_eq!(decode_batch(&frame.encode_to_vec()).unwrap(), batch);
+        }
+    }
+
+    #[test]
+    fn rejects_frames_above_the_max_batch_size() {
+        let encoded = vec![0u8; MAX_BATCH_SIZE + 1];
+        let zstd = TelemetryBatchFrame {
+            compression: Compression::Zstd as i32,
+            data: zstd::encode_all(encoded.as_slice(), ZSTD_LEVEL).unwrap(),
+        };
+        let lz4 = TelemetryBatchFrame {
+            compression: Compression::Lz4 as i32,
+            data: lz4_flex::compress_prepend_size(&encoded),
+        };
+        for frame in [zstd, lz4] {
+            let error = frame.decompress().unwrap_err();
+            assert_eq!(error.to_string(), too_large().to_string());
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_compressions() {
+        let frame = TelemetryBatchFrame {
+            compression: 42,
+            data: Vec::new(),
+        };
+        assert!(frame.decompress().is_err());
+    }
+}
@@ -10,6 +10,37 @@ pub mod vehicle_commands {
     include!(concat!(env!("OUT_DIR"), "/vehicle_commands.rs"));
 }
 
+pub mod vehicle_command_status {
+    include!(concat!(env!("OUT_DIR"), "/vehicle_command_status.rs"));
+}
+
+pub mod vehicle_telemetry_batch {
+    include!(concat!(env!("OUT_DIR"), "/vehicle_telemetry_batch.rs"));
+}
+
+// Compression and decoding of telemetry batches, for both ends of the uplink
+pub mod batch_codec;
+
+pub mod vehicle_twin_sync {
+    include!(concat!(env!("OUT_DIR"), "/vehicle_twin_sync.rs"));
+}
+
+// Field-level deltas of the Vehicle model, for both ends of the twin sync
+pub mod twin_delta;
+
+// Access to Vehicle model fields by VSS path
+pub mod vss_path;
+
+// Deserialization of messages from JSON that leaves out fields
+pub mod partial_json;
+
+pub mod vehicle_recording {
+    include!(concat!(env!("OUT_DIR"), "/vehicle_recording.rs"));
+}
+
+// Reading and writing of recorded Zenoh traffic
+pub mod recording;
+
 pub mod state {
     include!(concat!(env!("OUT_DIR"), "/intra.lock_state.rs"));
 }
@@ -37,3 +68,15 @@ pub mod exterior {
 pub mod current_location {
     include!(concat!(env!("OUT_DIR"), "/intra.current_location.rs"));
 }
+
+pub mod lights {
+    include!(concat!(env!("OUT_DIR"), "/intra.lights.rs"));
+}
+
+pub mod horn {
+    include!(concat!(env!("OUT_DIR"), "/intra.horn.rs"));
+}
+
+pub mod powertrain {
+    include!(concat!(env!("OUT_DIR"), "/intra.powertrain.rs"));
+}
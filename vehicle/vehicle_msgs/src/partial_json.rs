@@ -0,0 +1,291 @@
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess};
+use serde::de::{Deserializer, Visitor};
+use serde_json::{Error, Map, Value};
+
+// Deserializes a message from JSON that may leave out fields, e.g. a single
+// sub-tree of the twin. Left out fields get their default value, while fields
+// the message does not have are an error. Everywhere else messages are only
+// deserialized from complete JSON, so that a typo does not go unnoticed
+pub fn from_partial_value<T: DeserializeOwned>(value: Value) -> Result<T, Error> {
+    T::deserialize(Partial(Some(value)))
+}
+
+// A JSON value, or None for a field left out of its message
+struct Partial(Option<Value>);
+
+// Left out scalar fields deserialize from the JSON of their default value
+macro_rules! deserialize_or_default {
+    ($($method:ident => $default:expr),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.0.unwrap_or_else(|| $default).$method(visitor)
+            }
+        )*
+    };
+}
+
+// Non-finite floats are serialized as null, read them back as NaN
+macro_rules! deserialize_float {
+    ($($method:ident => $visit:ident: $float:ty),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.0 {
+                    None => visitor.$visit(0.0),
+                    Some(Value::Null) => visitor.$visit(<$float>::NAN),
+                    Some(value) => value.$method(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Partial {
+    type Error = Error;
+
+    deserialize_or_default! {
+        deserialize_any => Value::Null,
+        deserialize_bool => Value::Bool(false),
+        deserialize_i8 => Value::from(0),
+        deserialize_i16 => Value::from(0),
+        deserialize_i32 => Value::from(0),
+        deserialize_i64 => Value::from(0),
+        deserialize_u8 => Value::from(0),
+        deserialize_u16 => Value::from(0),
+        deserialize_u32 => Value::from(0),
+        deserialize_u64 => Value::from(0),
+        deserialize_char => Value::Null,
+        deserialize_str => Value::from(""),
+        deserialize_string => Value::from(""),
+        deserialize_bytes => Value::Array(Vec::new()),
+        deserialize_byte_buf => Value::Array(Vec::new()),
+        deserialize_unit => Value::Null,
+        deserialize_identifier => Value::Null,
+        deserialize_ignored_any => Value::Null,
+    }
+
+    deserialize_float! {
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            None | Some(Value::Null) => visitor.visit_none(),
+            Some(value) => visitor.visit_some(Partial(Some(value))),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0.unwrap_or_else(|| Value::Array(Vec::new())) {
+            Value::Array(items) => visitor.visit_seq(PartialSeq(items.into_iter())),
+            value => value.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0.unwrap_or_else(|| Value::Object(Map::new())) {
+            Value::Object(entries) => visitor.visit_map(PartialMap {
+                entries: entries.into_iter(),
+                value: None,
+            }),
+            value => value.deserialize_map(visitor),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0.unwrap_or_else(|| Value::Object(Map::new())) {
+            Value::Object(values) => visitor.visit_map(PartialStruct {
+                fields,
+                next: 0,
+                values,
+                value: None,
+            }),
+            value => value.deserialize_struct(name, fields, visitor),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.0
+            .unwrap_or(Value::Null)
+            .deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.0
+            .unwrap_or(Value::Null)
+            .deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.0
+            .unwrap_or(Value::Null)
+            .deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.0
+            .unwrap_or(Value::Null)
+            .deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.0
+            .unwrap_or(Value::Null)
+            .deserialize_enum(name, variants, visitor)
+    }
+}
+
+// Fields of a message, in the order the message declares them
+struct PartialStruct {
+    fields: &'static [&'static str],
+    next: usize,
+    values: Map<String, Value>,
+    value: Option<Value>,
+}
+
+impl<'de> MapAccess<'de> for PartialStruct {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        let Some(field) = self.fields.get(self.next) else {
+            return match self.values.keys().next() {
+                Some(unknown) => Err(de::Error::unknown_field(unknown, self.fields)),
+                None => Ok(None),
+            };
+        };
+        self.next += 1;
+        self.value = self.values.remove(*field);
+        seed.deserialize(IntoDeserializer::<Error>::into_deserializer(*field))
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(Partial(self.value.take()))
+    }
+}
+
+struct PartialMap {
+    entries: serde_json::map::IntoIter,
+    value: Option<Value>,
+}
+
+impl<'de> MapAccess<'de> for PartialMap {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        let Some((key, value)) = self.entries.next() else {
+            return Ok(None);
+        };
+        self.value = Some(value);
+        seed.deserialize(IntoDeserializer::<Error>::into_deserializer(key))
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(Partial(self.value.take()))
+    }
+}
+
+struct PartialSeq(std::vec::IntoIter<Value>);
+
+impl<'de> SeqAccess<'de> for PartialSeq {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.0
+            .next()
+            .map(|item| seed.deserialize(Partial(Some(item))))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vehicle_msgs::Vehicle;
+    use serde_json::json;
+
+    #[test]
+    fn defaults_left_out_fields() {
+        let vehicle: Vehicle = from_partial_value(json!({ "speed": 12.5 })).unwrap();
+        assert_eq!(vehicle.speed, 12.5);
+        assert!(!vehicle.is_moving);
+        assert!(vehicle.chassis.is_none());
+    }
+
+    #[test]
+    fn creates_nested_messages() {
+        let value = json!({ "chassis": { "axle": { "row1": { "wheel": { "left": { "tire": { "pressure": 240 } } } } } } });
+        let vehicle: Vehicle = from_partial_value(value).unwrap();
+        let tire = vehicle
+            .chassis
+            .unwrap()
+            .axle
+            .unwrap()
+            .row1
+            .unwrap()
+            .wheel
+            .unwrap()
+            .left
+            .unwrap()
+            .tire
+            .unwrap();
+        assert_eq!(tire.pressure, 240);
+        assert!(!tire.is_pressure_low);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let error = from_partial_value::<Vehicle>(json!({ "sped": 12.5 })).unwrap_err();
+        assert!(error.to_string().contains("unknown field `sped`"));
+        let nested = json!({ "diagnostics": { "dtc_count": 1, "dtc_cont": 1 } });
+        assert!(from_partial_value::<Vehicle>(nested).is_err());
+    }
+
+    #[test]
+    fn reads_null_floats_as_nan() {
+        let vehicle = Vehicle {
+            speed: f32::NAN,
+            ..Default::default()
+        };
+        let value = serde_json::to_value(&vehicle).unwrap();
+        assert_eq!(value["speed"], Value::Null);
+
+        let vehicle: Vehicle = from_partial_value(value).unwrap();
+        assert!(vehicle.speed.is_nan());
+        let vehicle: Vehicle = from_partial_value(json!({ "speed": null })).unwrap();
+        assert!(vehicle.speed.is_nan());
+    }
+}
@@ -0,0 +1,35 @@
+use crate::vehicle_recording::RecordedSample;
+use prost::Message;
+use std::io::Write;
+
+impl RecordedSample {
+    // Appends the sample to a recording
+    pub fn write_to(
+        &self,
+        writer: &mut impl Write,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        writer.write_all(&self.encode_length_delimited_to_vec())?;
+        Ok(())
+    }
+}
+
+// Decodes all samples of a recording. A sample cut off at the end, as left
+// by a recorder that was killed while writing, is dropped
+pub fn read_recording(
+    mut data: &[u8],
+) -> Result<Vec<RecordedSample>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut samples = Vec::new();
+    while !data.is_empty() {
+        let length = match prost::decode_length_delimiter(data) {
+            Ok(length) => length,
+            Err(_) => break,
+        };
+        let start = prost::length_delimiter_len(length);
+        if data.len() < start + length {
+            break;
+        }
+        samples.push(RecordedSample::decode(&data[start..start + length])?);
+        data = &data[start + length..];
+    }
+    Ok(samples)
+}
@@ -0,0 +1,129 @@
+use crate::partial_json::from_partial_value;
+use crate::vehicle_msgs::Vehicle;
+use crate::vehicle_twin_sync::{FieldDelta, VehicleDelta};
+use crate::vss_path::{VssPath, SYSTEM_STATE_FIELD};
+use serde_json::Value;
+
+impl VehicleDelta {
+    // Changes turning `old` into `new`, both being the JSON representation of
+    // a Vehicle. Sub-trees that were set or cleared are a single change
+    pub fn diff(old: &Value, new: &Value) -> VehicleDelta {
+        let mut changes = Vec::new();
+        diff_values("", old, new, &mut changes);
+        VehicleDelta { changes }
+    }
+
+    // Applies the changes to a copy of `vehicle`, creating the messages on
+    // the way to a changed field if they are not set yet
+    pub fn apply(
+        &self,
+        vehicle: &Vehicle,
+    ) -> Result<Vehicle, Box<dyn std::error::Error + Send + Sync>> {
+        let mut updated = vehicle.clone();
+        let mut values = Vec::with_capacity(self.changes.len());
+        for change in &self.changes {
+            let path: VssPath = change.path.parse()?;
+            let value = serde_json::from_str(&change.value)?;
+            // A mirror takes the system state the twin reports as it is
+            if path.fields() == [SYSTEM_STATE_FIELD] {
+                updated.low_voltage_system_state = from_partial_value(value)?;
+            } else {
+                values.push((path, value));
+            }
+        }
+        updated.set_paths(values)?;
+        Ok(updated)
+    }
+}
+
+fn diff_values(path: &str, old: &Value, new: &Value, changes: &mut Vec<FieldDelta>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, new_value) in new {
+                let path = join(path, key);
+                match old.get(key) {
+                    Some(old_value) => diff_values(&path, old_value, new_value, changes),
+                    None => changes.push(field_delta(path, new_value)),
+                }
+            }
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                changes.push(field_delta(join(path, key), &Value::Null));
+            }
+        }
+        _ if old != new => changes.push(field_delta(path.to_string(), new)),
+        _ => {}
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn field_delta(path: String, value: &Value) -> FieldDelta {
+    FieldDelta {
+        path,
+        value: value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partial_json::from_partial_value;
+    use serde_json::json;
+
+    fn vehicle(value: Value) -> Vehicle {
+        from_partial_value(value).unwrap()
+    }
+
+    fn round_trip(old: &Vehicle, new: &Vehicle) -> Vehicle {
+        let delta = VehicleDelta::diff(
+            &serde_json::to_value(old).unwrap(),
+            &serde_json::to_value(new).unwrap(),
+        );
+        delta.apply(old).unwrap()
+    }
+
+    #[test]
+    fn apply_of_diff_turns_old_into_new() {
+        let old = vehicle(json!({
+            "speed": 12.5,
+            "low_voltage_system_state": "LOCK",
+            "chassis": { "axle": { "row1": { "wheel": { "left": { "tire": { "pressure": 240 } } } } } },
+            "diagnostics": { "dtc_count": 2 },
+        }));
+        let new = vehicle(json!({
+            "speed": 30.0,
+            "is_moving": true,
+            "low_voltage_system_state": "START",
+            "chassis": { "axle": { "row1": { "wheel": { "left": { "tire": { "pressure": 250 } } } } } },
+            "body": { "lights": { "beam": { "low": { "is_on": true } } } },
+        }));
+        assert_eq!(round_trip(&old, &new), new);
+        assert_eq!(round_trip(&new, &old), old);
+    }
+
+    #[test]
+    fn cleared_sub_trees_are_one_change() {
+        let old = vehicle(json!({ "diagnostics": { "dtc_count": 1 } }));
+        let new = Vehicle::default();
+        let delta = VehicleDelta::diff(
+            &serde_json::to_value(&old).unwrap(),
+            &serde_json::to_value(&new).unwrap(),
+        );
+        assert_eq!(delta.changes.len(), 1);
+        assert_eq!(delta.changes[0].path, "diagnostics");
+        assert_eq!(delta.changes[0].value, "null");
+        assert_eq!(delta.apply(&old).unwrap(), new);
+    }
+
+    #[test]
+    fn unchanged_vehicles_have_no_changes() {
+        let value = serde_json::to_value(vehicle(json!({ "speed": 12.5 }))).unwrap();
+        assert!(VehicleDelta::diff(&value, &value).changes.is_empty());
+    }
+}
@@ -0,0 +1,303 @@
+use crate::partial_json::from_partial_value;
+use crate::vehicle_msgs::Vehicle;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::str::FromStr;
+
+// A field of the Vehicle model addressed by a VSS-style path such as
+// `Vehicle.Chassis.Axle.Row1.Wheel.Left.Tire.Pressure`, or by the equivalent
+// dotted path of model fields `chassis.axle.row1.wheel.left.tire.pressure`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VssPath {
+    fields: Vec<String>,
+}
+
+impl VssPath {
+    // Model field names from the root of the Vehicle down to the addressed one
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+}
+
+impl FromStr for VssPath {
+    type Err = String;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let mut segments: Vec<&str> = path.split('.').collect();
+        if segments.first() == Some(&"Vehicle") {
+            segments.remove(0);
+        }
+        if segments.is_empty() || segments.iter().any(|segment| segment.is_empty()) {
+            return Err(format!("Invalid VSS path '{}'", path));
+        }
+        Ok(Self {
+            fields: segments.into_iter().map(to_field_name).collect(),
+        })
+    }
+}
+
+impl fmt::Display for VssPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.fields.join("."))
+    }
+}
+
+// VSS names are PascalCase, the generated model fields snake_case, e.g.
+// `StateOfCharge` is `state_of_charge` and `DTCCount` is `dtc_count`
+fn to_field_name(segment: &str) -> String {
+    let chars: Vec<char> = segment.chars().collect();
+    let mut name = String::with_capacity(segment.len() + 4);
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_is_lowercase = chars
+                .get(i + 1)
+                .is_some_and(|next| next.is_ascii_lowercase());
+            if previous.is_ascii_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_ascii_uppercase() && next_is_lowercase)
+            {
+                name.push('_');
+            }
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name
+}
+
+// Field of the Vehicle that is only changed through the system state machine
+// of the twin, never by a path
+pub const SYSTEM_STATE_FIELD: &str = "low_voltage_system_state";
+
+// Typed access to the top-level fields of the Vehicle, so that a path only
+// goes through the JSON of the sub-tree it addresses
+macro_rules! vehicle_fields {
+    ($($field:ident),* $(,)?) => {
+        impl Vehicle {
+            fn field_value(&self, field: &str) -> Result<Value, String> {
+                match field {
+                    $(stringify!($field) => {
+                        serde_json::to_value(&self.$field).map_err(|e| e.to_string())
+                    })*
+                    _ => Err(format!("Unknown Vehicle field '{}'", field)),
+                }
+            }
+
+            fn set_field_value(
+                &mut self,
+                field: &str,
+                value: Value,
+            ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+                match field {
+                    $(stringify!($field) => self.$field = from_partial_value(value)?,)*
+                    _ => return Err(format!("Unknown Vehicle field '{}'", field).into()),
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+vehicle_fields! {
+    acceleration,
+    angular_velocity,
+    average_speed,
+    body,
+    chassis,
+    curb_weight,
+    current_location,
+    current_overall_weight,
+    diagnostics,
+    exterior,
+    is_broken_down,
+    is_moving,
+    low_voltage_battery,
+    low_voltage_system_state,
+    powertrain,
+    speed,
+    start_time,
+    traveled_distance,
+    traveled_distance_since_start,
+    trip_duration,
+    trip_meter_reading,
+    turning_diameter,
+    vehicle_identification,
+}
+
+impl Vehicle {
+    // Value of the field at `path`, None if it or a message on the way to it
+    // is not set or does not exist
+    pub fn get_path(&self, path: &VssPath) -> Option<Value> {
+        let (field, rest) = path.fields().split_first()?;
+        let value = self.field_value(field).ok()?;
+        let pointer = rest
+            .iter()
+            .map(|field| format!("/{}", field))
+            .collect::<String>();
+        value
+            .pointer(&pointer)
+            .filter(|value| !value.is_null())
+            .cloned()
+    }
+
+    pub fn set_path(
+        &mut self,
+        path: &VssPath,
+        value: Value,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.set_paths([(path.clone(), value)])
+    }
+
+    // Sets several fields at once, creating the messages on the way to them
+    // if they are not set yet. Nothing is changed if any of the paths does not
+    // exist, addresses the system state or a value does not fit the type of
+    // its field
+    pub fn set_paths(
+        &mut self,
+        values: impl IntoIterator<Item = (VssPath, Value)>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // The changed top-level fields with their updated JSON
+        let mut fields: Vec<(String, Value)> = Vec::new();
+        for (path, value) in values {
+            let (field, rest) = path
+                .fields()
+                .split_first()
+                .ok_or_else(|| format!("Invalid VSS path '{}'", path))?;
+            if field == SYSTEM_STATE_FIELD {
+                return Err(format!(
+                    "'{}' is only changed through the system state machine",
+                    SYSTEM_STATE_FIELD
+                )
+                .into());
+            }
+            let index = match fields.iter().position(|(name, _)| name == field) {
+                Some(index) => index,
+                None => {
+                    fields.push((field.clone(), self.field_value(field)?));
+                    fields.len() - 1
+                }
+            };
+            insert(&mut fields[index].1, rest, value, &path)?;
+        }
+        match fields.len() {
+            0 => {}
+            // A failed conversion leaves the field as it was
+            1 => {
+                let (field, value) = fields.remove(0);
+                self.set_field_value(&field, value)?;
+            }
+            _ => {
+                let mut updated = self.clone();
+                for (field, value) in fields {
+                    updated.set_field_value(&field, value)?;
+                }
+                *self = updated;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Sets the value at `fields` below `current`, turning unset messages into
+// empty ones on the way
+fn insert(
+    mut current: &mut Value,
+    fields: &[String],
+    value: Value,
+    path: &VssPath,
+) -> Result<(), String> {
+    for field in fields {
+        if current.is_null() {
+            *current = Value::Object(Map::new());
+        }
+        let Value::Object(object) = current else {
+            return Err(format!(
+                "'{}' is not a field of a message in '{}'",
+                field, path
+            ));
+        };
+        current = object.entry(field.as_str()).or_insert(Value::Null);
+    }
+    *current = value;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn path(path: &str) -> VssPath {
+        path.parse().unwrap()
+    }
+
+    #[test]
+    fn converts_vss_names_to_field_names() {
+        assert_eq!(to_field_name("DTCCount"), "dtc_count");
+        assert_eq!(to_field_name("Row1"), "row1");
+        assert_eq!(to_field_name("StateOfCharge"), "state_of_charge");
+        assert_eq!(
+            path("Vehicle.Chassis.Axle.Row1.Wheel.Left.Tire.Pressure"),
+            path("chassis.axle.row1.wheel.left.tire.pressure")
+        );
+        assert_eq!(
+            path("Vehicle.Diagnostics.DTCCount").to_string(),
+            "diagnostics.dtc_count"
+        );
+        assert!("Vehicle".parse::<VssPath>().is_err());
+        assert!("chassis..axle".parse::<VssPath>().is_err());
+    }
+
+    #[test]
+    fn creates_intermediate_messages() {
+        let mut vehicle = Vehicle::default();
+        let pressure = path("Vehicle.Chassis.Axle.Row1.Wheel.Left.Tire.Pressure");
+        assert_eq!(vehicle.get_path(&pressure), None);
+
+        vehicle.set_path(&pressure, json!(240)).unwrap();
+        assert_eq!(vehicle.get_path(&pressure), Some(json!(240)));
+        assert!(vehicle.chassis.is_some());
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let mut vehicle = Vehicle::default();
+        let typo = path("chassis.axle.row1.wheel.left.tire.presure");
+        assert!(vehicle.set_path(&typo, json!(240)).is_err());
+        assert!(vehicle.set_path(&path("Vehicle.Foo"), json!(1)).is_err());
+        assert!(vehicle.set_path(&path("speed.value"), json!(1)).is_err());
+        assert!(vehicle.chassis.is_none());
+    }
+
+    #[test]
+    fn refuses_the_system_state() {
+        let mut vehicle = Vehicle::default();
+        let system_state = path("Vehicle.LowVoltageSystemState");
+        assert!(vehicle.set_path(&system_state, json!("ON")).is_err());
+        assert_eq!(vehicle.low_voltage_system_state, "");
+    }
+
+    #[test]
+    fn failed_set_paths_leaves_the_vehicle_unchanged() {
+        let mut vehicle = Vehicle::default();
+        let result = vehicle.set_paths([
+            (path("Vehicle.Speed"), json!(12.5)),
+            (path("Vehicle.IsMoving"), json!(true)),
+            (
+                path("chassis.axle.row1.wheel.left.tire.pressure"),
+                json!("high"),
+            ),
+        ]);
+        assert!(result.is_err());
+        assert_eq!(vehicle, Vehicle::default());
+
+        vehicle
+            .set_paths([
+                (path("Vehicle.Speed"), json!(12.5)),
+                (path("Vehicle.IsMoving"), json!(true)),
+            ])
+            .unwrap();
+        assert_eq!(vehicle.speed, 12.5);
+        assert!(vehicle.is_moving);
+    }
+}